@@ -0,0 +1,261 @@
+// -------------------------------------------------------------------------------------------------
+// Format strings for `printf()`, which look like Rust's.  Each `{}` is replaced with the next
+// argument and may have a spec, `{:[[fill]align][sign][0][width][radix]}`, where `align` is one of
+// `<`, `^` or `>`, `sign` is `+`, and `radix` is one of `x`, `X`, `o` or `b`.  Literal braces are
+// written as `{{` and `}}`.
+//
+// The format string is parsed by the compiler, so any errors are found at compile time, and each
+// spec is packed into a word to be passed to the runtime, which does the formatting.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Align {
+    Default,
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Radix {
+    Decimal,
+    LowerHex,
+    UpperHex,
+    Octal,
+    Binary,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FormatSpec {
+    pub fill: char,
+    pub align: Align,
+    pub plus_sign: bool,
+    pub zero_pad: bool,
+    pub width: usize,
+    pub radix: Radix,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FormatPiece {
+    Text(String),
+    Arg(FormatSpec),
+}
+
+impl Default for FormatSpec {
+    fn default() -> Self {
+        FormatSpec {
+            fill: ' ',
+            align: Align::Default,
+            plus_sign: false,
+            zero_pad: false,
+            width: 0,
+            radix: Radix::Decimal,
+        }
+    }
+}
+
+pub fn parse_format(fmt: &str) -> Result<Vec<FormatPiece>, String> {
+    let mut pieces = Vec::new();
+    let mut text = String::new();
+    let mut chars = fmt.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                text.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                text.push('}');
+            }
+            '{' => {
+                let mut spec_str = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => spec_str.push(c),
+                        None => return Err(format!("unterminated `{{` in \"{}\"", fmt)),
+                    }
+                }
+                if !text.is_empty() {
+                    pieces.push(FormatPiece::Text(std::mem::take(&mut text)));
+                }
+                pieces.push(FormatPiece::Arg(parse_spec(&spec_str)?));
+            }
+            '}' => return Err(format!("unmatched `}}` in \"{}\"", fmt)),
+            _ => text.push(c),
+        }
+    }
+    if !text.is_empty() {
+        pieces.push(FormatPiece::Text(text));
+    }
+    Ok(pieces)
+}
+
+fn parse_spec(spec_str: &str) -> Result<FormatSpec, String> {
+    let mut spec = FormatSpec::default();
+    if spec_str.is_empty() {
+        return Ok(spec);
+    }
+    let bad_spec = || format!("invalid format spec `{{{}}}`", spec_str);
+    let chars = spec_str
+        .strip_prefix(':')
+        .ok_or_else(bad_spec)?
+        .chars()
+        .collect::<Vec<_>>();
+    let mut idx = 0;
+
+    let to_align = |c: Option<&char>| match c {
+        Some('<') => Some(Align::Left),
+        Some('^') => Some(Align::Center),
+        Some('>') => Some(Align::Right),
+        _ => None,
+    };
+    if let Some(align) = to_align(chars.get(1)) {
+        spec.fill = chars[0];
+        spec.align = align;
+        idx = 2;
+    } else if let Some(align) = to_align(chars.first()) {
+        spec.align = align;
+        idx = 1;
+    }
+
+    if chars.get(idx) == Some(&'+') {
+        spec.plus_sign = true;
+        idx += 1;
+    }
+    if chars.get(idx) == Some(&'0') {
+        spec.zero_pad = true;
+        idx += 1;
+    }
+    let width_start = idx;
+    while chars.get(idx).is_some_and(|c| c.is_ascii_digit()) {
+        idx += 1;
+    }
+    if idx > width_start {
+        let width = chars[width_start..idx].iter().collect::<String>();
+        spec.width = width.parse().map_err(|_| bad_spec())?;
+        if spec.width > u32::MAX as usize {
+            return Err(bad_spec());
+        }
+    }
+
+    spec.radix = match chars.get(idx) {
+        None => Radix::Decimal,
+        Some('x') => Radix::LowerHex,
+        Some('X') => Radix::UpperHex,
+        Some('o') => Radix::Octal,
+        Some('b') => Radix::Binary,
+        Some(_) => return Err(bad_spec()),
+    };
+    if chars.len() > idx + 1 {
+        return Err(bad_spec());
+    }
+    Ok(spec)
+}
+
+impl FormatSpec {
+    // Whether this spec only makes sense for numbers.
+    pub fn is_numeric(&self) -> bool {
+        self.plus_sign || self.zero_pad || self.radix != Radix::Decimal
+    }
+
+    // The spec is packed as: fill in bits 0-20, align in 21-22, the sign and zero pad flags in 23
+    // and 24, the radix in 25-27 and the width in 28-59.
+    pub fn to_word(self) -> i64 {
+        let align = match self.align {
+            Align::Default => 0,
+            Align::Left => 1,
+            Align::Center => 2,
+            Align::Right => 3,
+        };
+        let radix = match self.radix {
+            Radix::Decimal => 0,
+            Radix::LowerHex => 1,
+            Radix::UpperHex => 2,
+            Radix::Octal => 3,
+            Radix::Binary => 4,
+        };
+        (self.fill as i64)
+            | align << 21
+            | (self.plus_sign as i64) << 23
+            | (self.zero_pad as i64) << 24
+            | radix << 25
+            | (self.width as i64) << 28
+    }
+
+    pub fn from_word(word: i64) -> Self {
+        FormatSpec {
+            fill: char::from_u32((word & 0x1f_ffff) as u32).unwrap_or(' '),
+            align: match (word >> 21) & 3 {
+                0 => Align::Default,
+                1 => Align::Left,
+                2 => Align::Center,
+                _ => Align::Right,
+            },
+            plus_sign: (word >> 23) & 1 != 0,
+            zero_pad: (word >> 24) & 1 != 0,
+            radix: match (word >> 25) & 7 {
+                1 => Radix::LowerHex,
+                2 => Radix::UpperHex,
+                3 => Radix::Octal,
+                4 => Radix::Binary,
+                _ => Radix::Decimal,
+            },
+            width: ((word >> 28) & 0xffff_ffff) as usize,
+        }
+    }
+
+    pub fn radix_base(&self) -> u32 {
+        match self.radix {
+            Radix::Decimal => 10,
+            Radix::LowerHex | Radix::UpperHex => 16,
+            Radix::Octal => 8,
+            Radix::Binary => 2,
+        }
+    }
+
+    // Format a number given its sign and the digits of its magnitude.  Numbers are right aligned
+    // by default.
+    pub fn format_number(&self, negative: bool, digits: &str) -> String {
+        let digits = if self.radix == Radix::UpperHex {
+            digits.to_uppercase()
+        } else {
+            digits.to_string()
+        };
+        let sign = if negative {
+            "-"
+        } else if self.plus_sign {
+            "+"
+        } else {
+            ""
+        };
+        if self.zero_pad {
+            let len = sign.len() + digits.len();
+            let zeros = "0".repeat(self.width.saturating_sub(len));
+            format!("{}{}{}", sign, zeros, digits)
+        } else {
+            self.pad(&format!("{}{}", sign, digits), Align::Right)
+        }
+    }
+
+    // Pad a string to the width, which is left aligned by default.
+    pub fn format_str(&self, value: &str) -> String {
+        self.pad(value, Align::Left)
+    }
+
+    fn pad(&self, value: &str, default_align: Align) -> String {
+        let len = value.chars().count();
+        if len >= self.width {
+            return value.to_string();
+        }
+        let padding = self.width - len;
+        let (before, after) = match self.align {
+            Align::Default if default_align == Align::Right => (padding, 0),
+            Align::Right => (padding, 0),
+            Align::Center => (padding / 2, padding - padding / 2),
+            _ => (0, padding),
+        };
+        let fill = |n| std::iter::repeat_n(self.fill, n).collect::<String>();
+        format!("{}{}{}", fill(before), value, fill(after))
+    }
+}
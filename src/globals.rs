@@ -0,0 +1,110 @@
+// -------------------------------------------------------------------------------------------------
+// Globals are values given to the script by its host, e.g., with `-D name=value` on the command
+// line.  They're defined as variables before the script is compiled, so a script can use them
+// like any other variable.
+//
+// A definition may give the type of the value, as in `-D name:u8=7` or `-D name:str=7`.
+// Otherwise the value is an `i64` if it looks like one, and a string if not.
+//
+// A name which the script reads but never assigns anywhere can only be a global, so leaving it
+// undefined is reported before compiling.
+
+use std::collections::HashSet;
+
+use unicode_xid::UnicodeXID;
+
+use crate::kinds::children;
+use crate::parser::{AstNode, IntType};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum GlobalValue {
+    Int(IntType, i64),
+    Str(String),
+}
+
+pub fn parse_define(def: &str) -> Result<(String, GlobalValue), String> {
+    let (name, value) = def
+        .split_once('=')
+        .ok_or_else(|| format!("global definition `{}` must be `name=value`", def))?;
+    let (name, type_name) = match name.split_once(':') {
+        Some((name, type_name)) => (name, Some(type_name)),
+        None => (name, None),
+    };
+
+    let mut chars = name.chars();
+    let is_ident = chars
+        .next()
+        .is_some_and(|c| c == '_' || UnicodeXID::is_xid_start(c))
+        && chars.all(UnicodeXID::is_xid_continue);
+    if !is_ident {
+        return Err(format!("global name `{}` is not an identifier", name));
+    }
+
+    let value = match type_name {
+        Some("str") => GlobalValue::Str(value.to_string()),
+        Some(type_name) => {
+            let int_type = type_name.parse::<IntType>()?;
+            parse_int(name, value, int_type)?
+        }
+        None => match value.parse::<i64>() {
+            Ok(i) => GlobalValue::Int(IntType::I64, i),
+            Err(_) => GlobalValue::Str(value.to_string()),
+        },
+    };
+    Ok((name.to_string(), value))
+}
+
+fn parse_int(name: &str, value: &str, int_type: IntType) -> Result<GlobalValue, String> {
+    let i = value
+        .parse::<i128>()
+        .ok()
+        .filter(|i| *i >= int_type.min_value() && *i <= int_type.max_value())
+        .ok_or_else(|| {
+            format!(
+                "global `{}` value `{}` is not a valid {}",
+                name, value, int_type
+            )
+        })?;
+    Ok(GlobalValue::Int(int_type, i as i64))
+}
+
+// Return the first name read by `program` which is neither assigned anywhere nor in `globals`.
+pub fn find_missing(program: &[AstNode], globals: &[(String, GlobalValue)]) -> Option<String> {
+    let mut assigned = globals.iter().map(|(name, _)| name.clone()).collect();
+    let mut read = Vec::new();
+    for stmt in program {
+        collect_globals(stmt, &mut assigned, &mut read);
+    }
+    read.into_iter().find(|name| !assigned.contains(name))
+}
+
+fn collect_globals(node: &AstNode, assigned: &mut HashSet<String>, read: &mut Vec<String>) {
+    match node {
+        AstNode::Identifier(name) => read.push(name.clone()),
+        AstNode::Assign(name, _) | AstNode::Declare(name, _, _) => {
+            assigned.insert(name.clone());
+        }
+        AstNode::Destructure(names, _) => assigned.extend(names.iter().cloned()),
+        AstNode::For { ident, .. } => {
+            assigned.insert(ident.clone());
+        }
+        AstNode::Try {
+            catch: Some((name, _)),
+            ..
+        } => {
+            assigned.insert(name.clone());
+        }
+        AstNode::Closure { params, body } => {
+            // The params are only in scope within the body.
+            let mut body_read = Vec::new();
+            for stmt in body {
+                collect_globals(stmt, assigned, &mut body_read);
+            }
+            read.extend(body_read.into_iter().filter(|name| !params.contains(name)));
+        }
+        _ => (),
+    }
+    for child in children(node) {
+        collect_globals(child, assigned, read);
+    }
+}
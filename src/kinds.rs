@@ -0,0 +1,225 @@
+// -------------------------------------------------------------------------------------------------
+// Closure parameters and results are all passed as words, which are either ints or closures.
+// Which they are is inferred from the closure's body before it's compiled:
+//
+// - A parameter is a closure if it's called, if it's passed to a closure which expects a closure
+//   for that argument, or if it's assigned to or from a variable which is a closure.
+// - The result is whatever kind the `return` expressions are.
+//
+// Anything else is an int.  The inference doesn't look through calls of closures whose type is
+// unknown, e.g., a closure parameter, so those are assumed to return ints.
+
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use crate::parser::AstNode;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum WordKind {
+    Int,
+    // A closure, with its type if it's known.  A closure of unknown type may be passed ints or
+    // closures and returns an int.
+    Closure(Option<Rc<ClosureType>>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClosureType {
+    pub params: Vec<WordKind>,
+    pub ret: WordKind,
+}
+
+// `scope` has the kinds of the variables which may be captured.
+pub fn infer_closure_type(
+    params: &[String],
+    body: &[AstNode],
+    scope: &HashMap<String, WordKind>,
+) -> ClosureType {
+    let mut inference = Inference {
+        kinds: scope
+            .iter()
+            .map(|(name, kind)| (name.clone(), Some(kind.clone())))
+            .collect(),
+        aliases: HashSet::new(),
+        ret: None,
+        changed: true,
+    };
+    for param in params {
+        inference.kinds.insert(param.clone(), None);
+    }
+
+    // Each pass may find more closures, which may affect earlier statements, so keep going until
+    // nothing changes.
+    while inference.changed {
+        inference.changed = false;
+        inference.ret = None;
+        for stmt in body {
+            inference.infer(stmt, None);
+        }
+        for (lhs, rhs) in inference.aliases.clone() {
+            if let Some(kind @ WordKind::Closure(_)) = inference.kind_of(&lhs) {
+                inference.refine(&rhs, kind);
+            }
+            if let Some(kind @ WordKind::Closure(_)) = inference.kind_of(&rhs) {
+                inference.refine(&lhs, kind);
+            }
+        }
+    }
+
+    ClosureType {
+        params: params
+            .iter()
+            .map(|param| inference.kind_of(param).unwrap_or(WordKind::Int))
+            .collect(),
+        ret: inference.ret.unwrap_or(WordKind::Int),
+    }
+}
+
+struct Inference {
+    // The kind of each variable, or `None` if it isn't known yet.
+    kinds: HashMap<String, Option<WordKind>>,
+    // Variables assigned from other variables, which must be the same kind.
+    aliases: HashSet<(String, String)>,
+    ret: Option<WordKind>,
+    changed: bool,
+}
+
+impl Inference {
+    fn kind_of(&self, name: &str) -> Option<WordKind> {
+        self.kinds.get(name).cloned().flatten()
+    }
+
+    // Record what we've learnt about a variable, which only ever adds information.  A variable
+    // already known to be an int stays that way, and the compiler will complain about it.
+    fn refine(&mut self, name: &str, kind: WordKind) {
+        let refined = matches!(
+            (self.kind_of(name), &kind),
+            (None, _) | (Some(WordKind::Closure(None)), WordKind::Closure(Some(_)))
+        );
+        if refined {
+            self.kinds.insert(name.to_string(), Some(kind));
+            self.changed = true;
+        }
+    }
+
+    // The kind of an expression, if it's known.
+    fn expr_kind(&self, expr: &AstNode) -> Option<WordKind> {
+        match expr {
+            AstNode::Identifier(name) => self.kind_of(name),
+            AstNode::Closure { params, body } => {
+                let scope = self
+                    .kinds
+                    .iter()
+                    .filter_map(|(name, kind)| kind.clone().map(|kind| (name.clone(), kind)))
+                    .collect();
+                let closure_type = infer_closure_type(params, body, &scope);
+                Some(WordKind::Closure(Some(Rc::new(closure_type))))
+            }
+            AstNode::Call(name, _) => match self.kind_of(name) {
+                Some(WordKind::Closure(Some(closure_type))) => Some(closure_type.ret.clone()),
+                Some(WordKind::Closure(None)) => Some(WordKind::Int),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    // `shadowed` is set within nested closures, where only calls are of interest, and holds the
+    // nested closure's parameters.
+    fn infer(&mut self, node: &AstNode, shadowed: Option<&HashSet<String>>) {
+        let is_visible = |name: &String| shadowed.is_none_or(|shadowed| !shadowed.contains(name));
+        match node {
+            AstNode::Call(name, args) if is_visible(name) && self.kinds.contains_key(name) => {
+                self.refine(name, WordKind::Closure(None));
+                if let Some(WordKind::Closure(Some(closure_type))) = self.kind_of(name) {
+                    for (arg, param_kind) in args.iter().zip(&closure_type.params) {
+                        if let (AstNode::Identifier(arg_name), WordKind::Closure(_)) =
+                            (arg, param_kind)
+                        {
+                            if is_visible(arg_name) {
+                                self.refine(arg_name, param_kind.clone());
+                            }
+                        }
+                    }
+                }
+            }
+            AstNode::Assign(name, rhs) if shadowed.is_none() => {
+                if !self.kinds.contains_key(name) {
+                    self.kinds.insert(name.clone(), None);
+                }
+                if let Some(kind) = self.expr_kind(rhs) {
+                    self.refine(name, kind);
+                }
+                if let AstNode::Identifier(rhs_name) = &**rhs {
+                    self.aliases.insert((name.clone(), rhs_name.clone()));
+                }
+            }
+            AstNode::Return(expr) if shadowed.is_none() && self.ret.is_none() => {
+                self.ret = self.expr_kind(expr);
+            }
+            AstNode::Closure { params, body } => {
+                let mut inner_shadowed = shadowed.cloned().unwrap_or_default();
+                inner_shadowed.extend(params.iter().cloned());
+                for stmt in body {
+                    self.infer(stmt, Some(&inner_shadowed));
+                }
+                return;
+            }
+            _ => (),
+        }
+        for child in children(node) {
+            self.infer(child, shadowed);
+        }
+    }
+}
+
+// The nodes directly within `node`, other than the bodies of nested closures.
+pub fn children(node: &AstNode) -> Vec<&AstNode> {
+    match node {
+        AstNode::Stmt { stmt, .. } => vec![stmt],
+        AstNode::Literal(_) | AstNode::Identifier(_) | AstNode::Import(_) => Vec::new(),
+        AstNode::Closure { .. } => Vec::new(),
+        AstNode::Call(_, args) | AstNode::Tuple(args) => args.iter().collect(),
+        AstNode::Imported { body, .. } => body.iter().collect(),
+        AstNode::Cast(expr, _)
+        | AstNode::Assign(_, expr)
+        | AstNode::Declare(_, _, expr)
+        | AstNode::Destructure(_, expr)
+        | AstNode::Return(expr)
+        | AstNode::Throw(expr) => vec![expr],
+        AstNode::Index(expr, idx) => vec![expr, idx],
+        AstNode::Assert { cond, msg, .. } => {
+            std::iter::once(&**cond).chain(msg.as_deref()).collect()
+        }
+        AstNode::Try {
+            body,
+            catch,
+            finally,
+        } => body
+            .iter()
+            .chain(catch.iter().flat_map(|(_, catch_body)| catch_body))
+            .chain(finally.iter().flatten())
+            .collect(),
+        AstNode::If {
+            cond_expr,
+            true_expr,
+            false_expr,
+        } => std::iter::once(&**cond_expr)
+            .chain(true_expr)
+            .chain(false_expr)
+            .collect(),
+        AstNode::For {
+            first, last, body, ..
+        } => std::iter::once(&**first)
+            .chain(std::iter::once(&**last))
+            .chain(body)
+            .collect(),
+        AstNode::Switch {
+            expr,
+            cases,
+            default,
+        } => std::iter::once(&**expr)
+            .chain(cases.iter().flat_map(|(_, body)| body))
+            .chain(default)
+            .collect(),
+    }
+}
@@ -0,0 +1,3008 @@
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::panic::AssertUnwindSafe;
+use std::rc::Rc;
+use std::sync::Once;
+
+use cranelift::codegen::ir::{FuncRef, InstructionData, Opcode};
+use cranelift::frontend::Switch;
+use cranelift::prelude::*;
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{DataDescription, DataId, FuncId, Linkage, Module};
+
+mod format;
+mod globals;
+mod kinds;
+mod parser;
+mod runtime;
+
+pub use globals::{parse_define, GlobalValue};
+pub use parser::{parse_file, parse_program, AstNode, IntType};
+
+use format::{FormatPiece, FormatSpec, Radix};
+use kinds::{ClosureType, WordKind};
+use parser::{AstValue, ValueType};
+
+// -------------------------------------------------------------------------------------------------
+
+// Compile `program` and run it, with each of `globals` defined as a variable beforehand, and return
+// its exit status.  This is what the command line does, with `-D name=value` for each global.  A
+// script which fails to compile, has an uncaught runtime error or fails an `assert` returns an
+// error instead, and nothing is left behind for the next run.
+pub fn run(
+    program: &[AstNode],
+    globals: &[(String, GlobalValue)],
+    options: &RunOptions,
+) -> Result<i64, RunError> {
+    // Any name which is read but never assigned must be a global, so it's an error to leave it out.
+    if let Some(name) = globals::find_missing(program, globals) {
+        return Err(RunError::Compile(format!(
+            "global `{}` is used but not defined",
+            name
+        )));
+    }
+
+    runtime::reset(&options.args);
+    let (module, fn_main) = catch_compile_errors(|| compile(program, globals, options))?;
+
+    // Call the compiled binary (by casting it to extern "C" fn() -> i64, since it uses the
+    // platform's default calling convention).  If the script called `exit()` or failed then it
+    // has returned early, and the runtime has its result.
+    let code = module.get_finalized_function(fn_main);
+    let main_fn_ptr = unsafe { std::mem::transmute::<*const u8, extern "C" fn() -> i64>(code) };
+    let status = main_fn_ptr();
+    runtime::flush();
+    let result = runtime::take_exit().unwrap_or(Ok(status));
+
+    // Nothing the script left behind points into the module, so it can be freed.
+    unsafe { module.free_memory() };
+    result
+}
+
+fn compile(
+    program: &[AstNode],
+    globals: &[(String, GlobalValue)],
+    options: &RunOptions,
+) -> Result<(JITModule, FuncId), RunError> {
+    // Create a JIT module.
+    let mut jit_flags = settings::builder();
+    jit_flags.set("use_colocated_libcalls", "false").unwrap();
+    jit_flags.set("is_pic", "false").unwrap();
+    let isa_builder = cranelift_native::builder()
+        .map_err(|why| to_other_err(format!("host machine is not supported: {}", why)))?;
+    let mut builder = JITBuilder::with_isa(
+        isa_builder.finish(settings::Flags::new(jit_flags)).unwrap(),
+        cranelift_module::default_libcall_names(),
+    );
+    for (name, ptr) in runtime::symbols() {
+        builder.symbol(name, ptr);
+    }
+    let mut module = JITModule::new(builder);
+
+    // We have an implicit main() which takes nothing and returns the exit status.
+    let mut ctx = module.make_context();
+    ctx.func.signature = module.make_signature();
+    ctx.func.signature.returns.push(AbiParam::new(types::I64));
+    let fn_main = module
+        .declare_function("main", Linkage::Export, &ctx.func.signature)
+        .map_err(to_other_err)?;
+
+    // Build our main function.
+    let mut fn_ctx = FunctionBuilderContext::new();
+    let mut fn_builder = FunctionBuilder::new(&mut ctx.func, &mut fn_ctx);
+
+    // Gather and declare all our immediate strings.
+    let mut data_map = HashMap::<Vec<u8>, DataId>::new();
+    let mut str_id = 0_usize;
+    for stmt in program {
+        compile_data(&mut module, &mut data_map, &mut str_id, stmt);
+    }
+    module.finalize_definitions().map_err(to_other_err)?;
+
+    // The exception state is shared by all functions, see `Compiler::compile_throw()`.
+    let mut exc_state_descr = DataDescription::new();
+    exc_state_descr.define_zeroinit(EXC_STATE_WORDS * 8);
+    let exc_state = module
+        .declare_data("exception_state", Linkage::Local, true, false)
+        .map_err(to_other_err)?;
+    module
+        .define_data(exc_state, &exc_state_descr)
+        .map_err(to_other_err)?;
+
+    // Create the entry block.  Entry has no predecessors so we can seal it immediately.
+    let block = fn_builder.create_block();
+    fn_builder.switch_to_block(block);
+    fn_builder.seal_block(block);
+
+    // Compile the program.  Variables are declared as they're first assigned, once we know the
+    // shape of the value they hold.
+    let mut compiler = Compiler {
+        module: &mut module,
+        fn_builder,
+        data_map,
+        var_map: HashMap::new(),
+        var_id: 0,
+        in_closure: false,
+        ret_kind: WordKind::Int,
+        overflow: options.overflow,
+        padded_ints: options.padded_ints,
+        allow_env: options.allow_env,
+        line: 0,
+        file: None,
+        exc_state,
+        try_scopes: Vec::new(),
+        unwind_block: None,
+    };
+    for (name, value) in globals {
+        compiler.define_global(name, value);
+    }
+    for stmt in program {
+        compiler.compile_code(stmt);
+    }
+
+    // Finalize the main function.  Falling off the end is success.
+    let zero = compiler.fn_builder.ins().iconst(types::I64, 0);
+    compiler.fn_builder.ins().return_(&[zero]);
+    compiler.compile_unwind_block();
+    compiler.fn_builder.seal_all_blocks();
+    compiler.fn_builder.finalize();
+    module
+        .define_function(fn_main, &mut ctx)
+        .map_err(to_other_err)?;
+
+    // Link.
+    module.finalize_definitions().map_err(to_other_err)?;
+    Ok((module, fn_main))
+}
+
+// Compile errors are panics, which are caught and returned as `RunError::Compile`.  The panic hook
+// would print them as well, so it's skipped for panics while compiling.
+
+thread_local! {
+    static COMPILING: Cell<bool> = const { Cell::new(false) };
+}
+
+fn catch_compile_errors<T>(compile: impl FnOnce() -> Result<T, RunError>) -> Result<T, RunError> {
+    static QUIET_HOOK: Once = Once::new();
+    QUIET_HOOK.call_once(|| {
+        let hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            if !COMPILING.with(Cell::get) {
+                hook(info);
+            }
+        }));
+    });
+
+    COMPILING.with(|compiling| compiling.set(true));
+    let result = std::panic::catch_unwind(AssertUnwindSafe(compile));
+    COMPILING.with(|compiling| compiling.set(false));
+    match result {
+        Ok(result) => result,
+        Err(payload) => Err(RunError::Compile(
+            match (
+                payload.downcast_ref::<String>(),
+                payload.downcast_ref::<&str>(),
+            ) {
+                (Some(msg), _) => msg.clone(),
+                (None, Some(msg)) => msg.to_string(),
+                (None, None) => "compile error".to_string(),
+            },
+        )),
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+fn to_other_err<E>(err: E) -> std::io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    std::io::Error::other(err)
+}
+
+// -------------------------------------------------------------------------------------------------
+
+fn compile_data(
+    module: &mut JITModule,
+    data_map: &mut HashMap<Vec<u8>, DataId>,
+    str_id: &mut usize,
+    program: &AstNode,
+) {
+    match program {
+        AstNode::Stmt { stmt, .. } => compile_data(module, data_map, str_id, stmt),
+        AstNode::Literal(AstValue::Text(str_val)) => {
+            declare_imm_string(module, data_map, str_val, str_id)
+        }
+        AstNode::Literal(_) => (),
+        AstNode::Identifier(_) => (),
+        AstNode::Call(_, args) => args
+            .iter()
+            .for_each(|arg| compile_data(module, data_map, str_id, arg)),
+        AstNode::Tuple(elems) => elems
+            .iter()
+            .for_each(|elem| compile_data(module, data_map, str_id, elem)),
+        AstNode::Cast(box_expr, _) => compile_data(module, data_map, str_id, box_expr),
+        AstNode::Index(box_expr, box_idx) => {
+            compile_data(module, data_map, str_id, box_expr);
+            compile_data(module, data_map, str_id, box_idx);
+        }
+        AstNode::Assign(_, box_rhs) => compile_data(module, data_map, str_id, box_rhs),
+        AstNode::Declare(_, _, box_rhs) => compile_data(module, data_map, str_id, box_rhs),
+        AstNode::Destructure(_, box_rhs) => compile_data(module, data_map, str_id, box_rhs),
+        AstNode::Closure { body, .. } => {
+            for stmt in body {
+                compile_data(module, data_map, str_id, stmt);
+            }
+        }
+        AstNode::Return(box_expr) => compile_data(module, data_map, str_id, box_expr),
+        AstNode::Throw(box_expr) => compile_data(module, data_map, str_id, box_expr),
+        AstNode::Assert { cond, msg, .. } => {
+            compile_data(module, data_map, str_id, cond);
+            if let Some(msg) = msg {
+                compile_data(module, data_map, str_id, msg);
+            }
+        }
+        AstNode::Try {
+            body,
+            catch,
+            finally,
+        } => {
+            let catch_body = catch.iter().flat_map(|(_, catch_body)| catch_body);
+            for stmt in body
+                .iter()
+                .chain(catch_body)
+                .chain(finally.iter().flatten())
+            {
+                compile_data(module, data_map, str_id, stmt);
+            }
+        }
+        AstNode::Import(_) => unreachable!("Imports are resolved by the parser."),
+        AstNode::Imported { body, .. } => {
+            for stmt in body {
+                compile_data(module, data_map, str_id, stmt);
+            }
+        }
+        AstNode::If {
+            cond_expr,
+            true_expr,
+            false_expr,
+        } => {
+            compile_data(module, data_map, str_id, cond_expr);
+            for stmt in true_expr {
+                compile_data(module, data_map, str_id, stmt);
+            }
+            for stmt in false_expr {
+                compile_data(module, data_map, str_id, stmt);
+            }
+        }
+        AstNode::For {
+            first, last, body, ..
+        } => {
+            compile_data(module, data_map, str_id, first);
+            compile_data(module, data_map, str_id, last);
+            for stmt in body {
+                compile_data(module, data_map, str_id, stmt);
+            }
+        }
+        AstNode::Switch {
+            expr,
+            cases,
+            default,
+        } => {
+            compile_data(module, data_map, str_id, expr);
+            for (_, body) in cases {
+                for stmt in body {
+                    compile_data(module, data_map, str_id, stmt);
+                }
+            }
+            for stmt in default {
+                compile_data(module, data_map, str_id, stmt);
+            }
+        }
+    }
+}
+
+fn declare_imm_string(
+    module: &mut JITModule,
+    data_map: &mut HashMap<Vec<u8>, DataId>,
+    str_val: &[u8],
+    str_id: &mut usize,
+) {
+    let mut data_descr = DataDescription::new();
+    data_descr.define(str_val.to_owned().into_boxed_slice());
+
+    let name = format!("str_{}", str_id);
+    *str_id += 1;
+
+    let id = module
+        .declare_data(&name, Linkage::Export, false, false)
+        .expect("Declaring a global string immediate.");
+    module
+        .define_data(id, &data_descr)
+        .expect("Defining a global string immediate.");
+
+    data_map.insert(str_val.to_owned(), id);
+}
+
+// -------------------------------------------------------------------------------------------------
+
+// Gather every name referenced within `node`, to find the variables a closure captures.
+
+fn collect_names(node: &AstNode, used: &mut HashSet<String>) {
+    match node {
+        AstNode::Stmt { stmt, .. } => collect_names(stmt, used),
+        AstNode::Literal(_) => (),
+        AstNode::Identifier(name) => {
+            used.insert(name.clone());
+        }
+        AstNode::Call(name, args) => {
+            used.insert(name.clone());
+            collect_names_list(args, used);
+        }
+        AstNode::Tuple(elems) => collect_names_list(elems, used),
+        AstNode::Cast(box_expr, _) => collect_names(box_expr, used),
+        AstNode::Index(box_expr, box_idx) => {
+            collect_names(box_expr, used);
+            collect_names(box_idx, used);
+        }
+        AstNode::Assign(_, box_rhs) => collect_names(box_rhs, used),
+        AstNode::Declare(_, _, box_rhs) => collect_names(box_rhs, used),
+        AstNode::Destructure(_, box_rhs) => collect_names(box_rhs, used),
+        AstNode::Closure { params, body } => {
+            // Names used by a nested closure must be captured by this one too, except for the
+            // nested closure's own parameters.
+            let mut inner_used = HashSet::new();
+            collect_names_list(body, &mut inner_used);
+            for name in inner_used {
+                if !params.contains(&name) {
+                    used.insert(name);
+                }
+            }
+        }
+        AstNode::Return(box_expr) => collect_names(box_expr, used),
+        AstNode::Throw(box_expr) => collect_names(box_expr, used),
+        AstNode::Assert { cond, msg, .. } => {
+            collect_names(cond, used);
+            if let Some(msg) = msg {
+                collect_names(msg, used);
+            }
+        }
+        AstNode::Try {
+            body,
+            catch,
+            finally,
+        } => {
+            collect_names_list(body, used);
+            if let Some((_, catch_body)) = catch {
+                collect_names_list(catch_body, used);
+            }
+            if let Some(finally_body) = finally {
+                collect_names_list(finally_body, used);
+            }
+        }
+        AstNode::Import(_) => (),
+        AstNode::Imported { body, .. } => collect_names_list(body, used),
+        AstNode::If {
+            cond_expr,
+            true_expr,
+            false_expr,
+        } => {
+            collect_names(cond_expr, used);
+            collect_names_list(true_expr, used);
+            collect_names_list(false_expr, used);
+        }
+        AstNode::For {
+            first, last, body, ..
+        } => {
+            collect_names(first, used);
+            collect_names(last, used);
+            collect_names_list(body, used);
+        }
+        AstNode::Switch {
+            expr,
+            cases,
+            default,
+        } => {
+            collect_names(expr, used);
+            for (_, body) in cases {
+                collect_names_list(body, used);
+            }
+            collect_names_list(default, used);
+        }
+    }
+}
+
+fn collect_names_list(nodes: &[AstNode], used: &mut HashSet<String>) {
+    for node in nodes {
+        collect_names(node, used);
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+// A compiled value.  Tuples aren't given any storage of their own, their elements are just held in
+// SSA values (or in variables, see `VarSlot`) and passed around together.
+
+#[derive(Clone, Debug)]
+enum CodeValue {
+    Int(IntType, Value),
+    // A pointer to a null terminated string.
+    Str(Value),
+    // A pointer to a runtime bigint.
+    BigInt(Value),
+    // A pointer to a runtime array of strings.
+    StrArray(Value),
+    // A pointer to a closure's environment, and the closure's type if it's known.
+    Closure(Value, Option<Rc<ClosureType>>),
+    Tuple(Vec<CodeValue>),
+}
+
+// A declared variable, which has the same shape as the value it was first assigned.
+
+#[derive(Clone, Debug)]
+enum VarSlot {
+    Int(IntType, Variable),
+    Str(Variable),
+    BigInt(Variable),
+    StrArray(Variable),
+    Closure(Variable, Option<Rc<ClosureType>>),
+    Tuple(Vec<VarSlot>),
+}
+
+const INT_OPERATORS: &[&str] = &[
+    "+", "-", "*", "/", "%", "&", "|", "^", "~", "<<", ">>", ">>>",
+];
+
+// The math builtins, which take ints of one type and return that type, with their argument counts.
+const MATH_BUILTINS: &[(&str, usize)] = &[
+    ("abs", 1),
+    ("min", 2),
+    ("max", 2),
+    ("pow", 2),
+    ("gcd", 2),
+    ("lcm", 2),
+    ("clamp", 3),
+    ("isqrt", 1),
+    ("sign", 1),
+];
+
+// The string builtins, with their argument counts.
+const STRING_BUILTINS: &[(&str, usize)] = &[
+    ("len", 1),
+    ("substr", 3),
+    ("find", 2),
+    ("replace", 3),
+    ("split", 2),
+    ("trim", 1),
+    ("upper", 1),
+    ("lower", 1),
+    ("starts_with", 2),
+    ("repeat", 2),
+    ("to_string", 1),
+    ("parse_int", 1),
+];
+
+fn is_output_builtin(name: &str) -> bool {
+    matches!(name, "write" | "eprint" | "ewrite")
+}
+
+fn builtin_arg_count(builtins: &[(&str, usize)], name: &str) -> Option<usize> {
+    builtins
+        .iter()
+        .find(|(builtin, _)| *builtin == name)
+        .map(|(_, count)| *count)
+}
+
+fn check_arg_count(name: &str, args: &[AstNode], arg_count: usize) {
+    if args.len() != arg_count {
+        panic!(
+            "{}() takes {} argument{}, not {}.",
+            name,
+            arg_count,
+            if arg_count == 1 { "" } else { "s" },
+            args.len()
+        );
+    }
+}
+
+// Whether `expr` is an int literal or arithmetic using only int literals.
+fn is_untyped_int(expr: &AstNode) -> bool {
+    match expr {
+        AstNode::Literal(AstValue::Int(_)) => true,
+        AstNode::Call(name, args)
+            if INT_OPERATORS.contains(&name.as_str())
+                || builtin_arg_count(MATH_BUILTINS, name).is_some() =>
+        {
+            args.iter().all(is_untyped_int)
+        }
+        _ => false,
+    }
+}
+
+// Evaluate arithmetic on only int literals exactly, so it can be range checked for the type it's
+// used as.  Returns `None` if it can't be evaluated at compile time, e.g., for a division by zero,
+// which is left to fail at runtime.
+fn fold_untyped_int(expr: &AstNode) -> Option<i128> {
+    let (name, args) = match expr {
+        AstNode::Literal(AstValue::Int(i)) => return Some(*i),
+        AstNode::Call(name, args) => (name.as_str(), args),
+        _ => return None,
+    };
+    let args = args
+        .iter()
+        .map(fold_untyped_int)
+        .collect::<Option<Vec<_>>>()?;
+    let shift = || u32::try_from(args[1]).ok();
+    match (name, args.as_slice()) {
+        ("~", [x]) => Some(!x),
+        ("abs", [x]) => x.checked_abs(),
+        ("sign", [x]) => Some(x.signum()),
+        ("isqrt", [x]) if *x >= 0 => Some(x.isqrt()),
+        ("+", [x, y]) => x.checked_add(*y),
+        ("-", [x, y]) => x.checked_sub(*y),
+        ("*", [x, y]) => x.checked_mul(*y),
+        ("/", [x, y]) => x.checked_div(*y),
+        ("%", [x, y]) => x.checked_rem(*y),
+        ("&", [x, y]) => Some(x & y),
+        ("|", [x, y]) => Some(x | y),
+        ("^", [x, y]) => Some(x ^ y),
+        // As at runtime, `<<` is multiplication, and shifting by 64 or more shifts out every bit.
+        // Any non-zero `x << 64` is out of range for every type.
+        ("<<", [x, _]) => x.checked_mul(1 << shift()?.min(64)),
+        (">>", [x, _]) => Some(x >> shift()?.min(127)),
+        (">>>", [x, _]) => {
+            let x = i64::try_from(*x).ok()? as u64;
+            Some(x.checked_shr(shift()?).unwrap_or(0) as i128)
+        }
+        ("min", [x, y]) => Some(*x.min(y)),
+        ("max", [x, y]) => Some(*x.max(y)),
+        ("pow", [x, y]) => x.checked_pow(u32::try_from(*y).ok()?),
+        ("gcd", [x, y]) => Some(gcd_i128(*x, *y)),
+        ("lcm", [x, y]) => {
+            let gcd = gcd_i128(*x, *y);
+            if gcd == 0 {
+                Some(0)
+            } else {
+                (x / gcd).checked_mul(*y)?.checked_abs()
+            }
+        }
+        ("clamp", [x, lo, hi]) if lo <= hi => Some(*x.clamp(lo, hi)),
+        _ => None,
+    }
+}
+
+fn gcd_i128(x: i128, y: i128) -> i128 {
+    let (mut a, mut b) = (x.unsigned_abs(), y.unsigned_abs());
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a as i128
+}
+
+fn word_to_value(kind: &WordKind, word: Value) -> CodeValue {
+    match kind {
+        WordKind::Int => CodeValue::Int(IntType::I64, word),
+        WordKind::Closure(closure_type) => CodeValue::Closure(word, closure_type.clone()),
+    }
+}
+
+fn int_cl_type(int_type: IntType) -> Type {
+    Type::int(int_type.bits() as u16).unwrap()
+}
+
+fn slot_word_count(slot: &VarSlot) -> i64 {
+    match slot {
+        VarSlot::Tuple(slots) => slots.iter().map(slot_word_count).sum(),
+        _ => 1,
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+// A `try` block which is being compiled.  Exceptions thrown within it jump to `handler`, and
+// returning from within it must first run the `finally` body, if there is one.
+
+#[derive(Clone, Debug)]
+struct TryScope {
+    handler: Block,
+    finally: Option<Vec<AstNode>>,
+}
+
+// The exception state holds whether an exception is pending, the thrown message string and the
+// location it was thrown from, which is only used for reporting uncaught exceptions.  The pending
+// flag is `EXC_HALTED` instead when the script is returning from a closure to exit.
+
+const EXC_STATE_WORDS: usize = 3;
+const EXC_PENDING_OFFSET: i32 = 0;
+const EXC_MESSAGE_OFFSET: i32 = 8;
+const EXC_LOCATION_OFFSET: i32 = 16;
+const EXC_HALTED: i64 = 2;
+
+// The compiled operands of a binary operator.
+
+enum Operands {
+    Int(IntType, Value, Value),
+    BigInt(Value, Value),
+}
+
+// Where formatted output goes.
+
+#[derive(Clone, Copy, Debug)]
+struct OutputTarget {
+    to_stderr: bool,
+    newline: bool,
+}
+
+// How arithmetic which overflows its int type is handled.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OverflowMode {
+    Wrap,
+    Trap,
+    Saturate,
+}
+
+// The settings for `run()`, which the command line options choose.
+
+#[derive(Clone, Debug)]
+pub struct RunOptions {
+    pub overflow: OverflowMode,
+    // Print ints the old way, as their last three digits padded with spaces.
+    pub padded_ints: bool,
+    // Whether scripts may read environment variables, which sandboxed runs can refuse.
+    pub allow_env: bool,
+    // The script's arguments, returned by `args()`.
+    pub args: Vec<String>,
+}
+
+// Why `run()` failed.  The command line prints the message and exits with `exit_status()`.
+
+#[derive(Debug)]
+pub enum RunError {
+    // The program can't be compiled, e.g., it has a type error or a global is missing.
+    Compile(String),
+    // The script stopped with an uncaught runtime error.
+    Runtime(String),
+    // An `assert` in the script failed.
+    Assert(String),
+    // The JIT couldn't build the program.
+    Io(std::io::Error),
+}
+
+impl RunError {
+    pub fn exit_status(&self) -> i32 {
+        match self {
+            RunError::Compile(_) => 101,
+            RunError::Runtime(_) => 2,
+            RunError::Assert(_) => 3,
+            RunError::Io(_) => 1,
+        }
+    }
+}
+
+impl std::fmt::Display for RunError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RunError::Compile(msg) => write!(f, "Compile error: {}", msg),
+            RunError::Runtime(msg) | RunError::Assert(msg) => write!(f, "{}", msg),
+            RunError::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for RunError {}
+
+impl From<std::io::Error> for RunError {
+    fn from(err: std::io::Error) -> Self {
+        RunError::Io(err)
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+struct Compiler<'a> {
+    module: &'a mut JITModule,
+    fn_builder: FunctionBuilder<'a>,
+    data_map: HashMap<Vec<u8>, DataId>,
+    var_map: HashMap<String, VarSlot>,
+    var_id: usize,
+    in_closure: bool,
+    // The kind of word `return` must be given, which is always an int at the top level.
+    ret_kind: WordKind,
+    overflow: OverflowMode,
+    padded_ints: bool,
+    // Whether scripts may read environment variables, which sandboxed runs can refuse.
+    allow_env: bool,
+    // The line of the statement being compiled, and its file if it was imported, for runtime error
+    // messages.
+    line: usize,
+    file: Option<String>,
+    exc_state: DataId,
+    try_scopes: Vec<TryScope>,
+    // Where uncaught exceptions go, which is created on demand.
+    unwind_block: Option<Block>,
+}
+
+impl<'a> Compiler<'a> {
+    fn compile_code(&mut self, program: &AstNode) -> CodeValue {
+        match program {
+            AstNode::Stmt { line, stmt } => {
+                self.line = *line;
+                self.compile_code(stmt)
+            }
+            AstNode::Literal(AstValue::Int(_)) => {
+                CodeValue::Int(IntType::I64, self.compile_int_as(program, IntType::I64))
+            }
+            AstNode::Literal(AstValue::BigInt(digits)) => {
+                let digits = format!("{}\0", digits);
+                let data_id = self.imm_string(digits.as_bytes());
+                let digits_ptr = self.data_address(data_id);
+                let bigint = self.call_runtime("fbl_bigint_parse", &[digits_ptr]);
+                CodeValue::BigInt(bigint)
+            }
+            AstNode::Literal(AstValue::Text(s)) => {
+                let data_id = self.imm_string(s);
+                CodeValue::Str(self.data_address(data_id))
+            }
+            AstNode::Identifier(name) => {
+                let slot = self
+                    .var_map
+                    .get(name)
+                    .unwrap_or_else(|| panic!("Undefined variable: {}", name))
+                    .clone();
+                self.use_slot(&slot)
+            }
+            AstNode::Tuple(elems) => {
+                CodeValue::Tuple(elems.iter().map(|elem| self.compile_code(elem)).collect())
+            }
+            AstNode::Cast(expr, ValueType::Int(int_type)) => match self.compile_code(expr) {
+                CodeValue::Int(from_type, value) => {
+                    CodeValue::Int(*int_type, self.convert_int(value, from_type, *int_type))
+                }
+                CodeValue::BigInt(value) => {
+                    let value = self.call_runtime("fbl_bigint_to_i64", &[value]);
+                    CodeValue::Int(*int_type, self.convert_int(value, IntType::I64, *int_type))
+                }
+                value => panic!("Expecting an integer, found {:?}.", value),
+            },
+            AstNode::Cast(expr, ValueType::BigInt) => CodeValue::BigInt(self.compile_bigint(expr)),
+            AstNode::Index(expr, idx) => self.compile_index(expr, idx),
+            AstNode::Call(name, args) => self.compile_call(name, args),
+            AstNode::Assign(name, expr) => {
+                // An int literal assigned to an existing int variable takes on its type.
+                let rhs_value = match self.var_map.get(name) {
+                    Some(VarSlot::Int(int_type, _)) => {
+                        let int_type = *int_type;
+                        CodeValue::Int(int_type, self.compile_int_as(expr, int_type))
+                    }
+                    Some(VarSlot::BigInt(_)) => CodeValue::BigInt(self.compile_bigint(expr)),
+                    _ => self.compile_code(expr),
+                };
+                self.assign_variable(name, &rhs_value);
+                rhs_value
+            }
+            AstNode::Declare(name, value_type, expr) => {
+                let rhs_value = match value_type {
+                    ValueType::Int(int_type) => {
+                        CodeValue::Int(*int_type, self.compile_int_as(expr, *int_type))
+                    }
+                    ValueType::BigInt => CodeValue::BigInt(self.compile_bigint(expr)),
+                };
+                self.assign_variable(name, &rhs_value);
+                rhs_value
+            }
+            AstNode::Destructure(names, expr) => {
+                // The RHS is entirely evaluated before any assignments, so swaps like
+                // `(a, b) = (b, a);` work as expected.
+                let rhs_value = self.compile_code(expr);
+                match &rhs_value {
+                    CodeValue::Tuple(elems) if elems.len() == names.len() => {
+                        for (name, elem) in names.iter().zip(elems) {
+                            self.assign_variable(name, elem);
+                        }
+                    }
+                    _ => panic!(
+                        "Cannot destructure {:?} into {} variables.",
+                        rhs_value,
+                        names.len()
+                    ),
+                }
+                rhs_value
+            }
+            AstNode::If {
+                cond_expr,
+                true_expr,
+                false_expr,
+            } => self.compile_if(cond_expr, true_expr, false_expr),
+            AstNode::For {
+                ident,
+                first,
+                last,
+                body,
+            } => self.compile_for(ident, first, last, body),
+            AstNode::Switch {
+                expr,
+                cases,
+                default,
+            } => self.compile_switch(expr, cases, default),
+            AstNode::Closure { params, body } => self.compile_closure(params, body),
+            AstNode::Return(expr) => self.compile_return(expr),
+            AstNode::Throw(expr) => {
+                let msg = match self.compile_code(expr) {
+                    CodeValue::Str(msg) => msg,
+                    value => panic!("Only strings may be thrown, found {:?}.", value),
+                };
+                self.compile_throw(msg);
+                self.null_value()
+            }
+            AstNode::Try {
+                body,
+                catch,
+                finally,
+            } => self.compile_try(body, catch, finally),
+            AstNode::Assert {
+                cond,
+                cond_text,
+                msg,
+            } => self.compile_assert(cond, cond_text, msg),
+            AstNode::Imported { path, body } => {
+                let file = self.file.replace(path.clone());
+                for stmt in body {
+                    self.compile_code(stmt);
+                }
+                self.file = file;
+                self.null_value()
+            }
+
+            _ => panic!("unhandled node: {:?}", program),
+        }
+    }
+
+    fn compile_int(&mut self, expr: &AstNode) -> Value {
+        self.compile_typed_int(expr).1
+    }
+
+    fn compile_typed_int(&mut self, expr: &AstNode) -> (IntType, Value) {
+        match self.compile_code(expr) {
+            CodeValue::Int(int_type, value) => (int_type, value),
+            value => panic!("Expecting an integer, found {:?}.", value),
+        }
+    }
+
+    // Compile an int expression which must be of type `int_type`.  Literals are untyped and are
+    // converted, as long as they're in range, but anything else must be cast explicitly.
+    fn compile_int_as(&mut self, expr: &AstNode, int_type: IntType) -> Value {
+        match expr {
+            AstNode::Literal(AstValue::Int(i)) => {
+                let i = *i;
+                if i < int_type.min_value() || i > int_type.max_value() {
+                    panic!("Literal {} is out of range for {}.", i, int_type);
+                }
+                self.fn_builder
+                    .ins()
+                    .iconst(int_cl_type(int_type), i as i64)
+            }
+            _ if is_untyped_int(expr) => {
+                // Arithmetic on only literals, e.g., `0 - 7`, can be converted too, as long as its
+                // value is in range.  If it can't be folded then it's i64 and fails at runtime.
+                if let Some(i) = fold_untyped_int(expr) {
+                    if i < int_type.min_value() || i > int_type.max_value() {
+                        panic!("Constant {} is out of range for {}.", i, int_type);
+                    }
+                    return self
+                        .fn_builder
+                        .ins()
+                        .iconst(int_cl_type(int_type), i as i64);
+                }
+                let value = self.compile_int(expr);
+                self.convert_int(value, IntType::I64, int_type)
+            }
+            _ => {
+                let (value_type, value) = self.compile_typed_int(expr);
+                if value_type != int_type {
+                    panic!(
+                        "Expecting {} but found {}, use `as` to convert.",
+                        int_type, value_type
+                    );
+                }
+                value
+            }
+        }
+    }
+
+    // Compile the operands of a binary operator, which must be the same type.  A literal operand
+    // takes on the type of the other.
+    fn compile_int_operands(&mut self, lhs: &AstNode, rhs: &AstNode) -> (IntType, Value, Value) {
+        match self.compile_operands(lhs, rhs) {
+            Operands::Int(int_type, lhs_val, rhs_val) => (int_type, lhs_val, rhs_val),
+            Operands::BigInt(..) => panic!("Expecting integer operands, found bigints."),
+        }
+    }
+
+    // As above, except if either operand is a bigint then the other is converted to a bigint too.
+    fn compile_operands(&mut self, lhs: &AstNode, rhs: &AstNode) -> Operands {
+        let (untyped, other) = if is_untyped_int(lhs) {
+            (lhs, rhs)
+        } else {
+            (rhs, lhs)
+        };
+        let other_val = self.compile_code(other);
+        let (int_type, other_val, untyped_val) = match other_val {
+            CodeValue::Int(int_type, other_val) => {
+                (int_type, other_val, self.compile_int_as(untyped, int_type))
+            }
+            CodeValue::BigInt(other_val) => {
+                let untyped_val = self.compile_bigint(untyped);
+                return if is_untyped_int(lhs) {
+                    Operands::BigInt(untyped_val, other_val)
+                } else {
+                    Operands::BigInt(other_val, untyped_val)
+                };
+            }
+            value => panic!("Expecting an integer, found {:?}.", value),
+        };
+        if is_untyped_int(lhs) {
+            Operands::Int(int_type, untyped_val, other_val)
+        } else {
+            Operands::Int(int_type, other_val, untyped_val)
+        }
+    }
+
+    fn convert_int(&mut self, value: Value, from_type: IntType, to_type: IntType) -> Value {
+        let to_cl_type = int_cl_type(to_type);
+        if to_type.bits() > from_type.bits() {
+            if from_type.is_signed() {
+                self.fn_builder.ins().sextend(to_cl_type, value)
+            } else {
+                self.fn_builder.ins().uextend(to_cl_type, value)
+            }
+        } else if to_type.bits() < from_type.bits() {
+            self.fn_builder.ins().ireduce(to_cl_type, value)
+        } else {
+            // Only the signedness differs, which doesn't change the bits.
+            value
+        }
+    }
+
+    fn bool_to_int(&mut self, cmp_val: Value) -> CodeValue {
+        // Since we don't have a boolean type (or any type safety) we need to convert this result
+        // back to an int.
+        CodeValue::Int(
+            IntType::I64,
+            self.fn_builder.ins().uextend(types::I64, cmp_val),
+        )
+    }
+
+    // As above, but to a particular int type.  Comparisons already produce an `i8`.
+    fn bool_to_type(&mut self, cmp_val: Value, int_type: IntType) -> Value {
+        if int_type.bits() == 8 {
+            cmp_val
+        } else {
+            self.fn_builder
+                .ins()
+                .uextend(int_cl_type(int_type), cmp_val)
+        }
+    }
+
+    fn null_value(&mut self) -> CodeValue {
+        CodeValue::Int(IntType::I64, self.fn_builder.ins().iconst(types::I64, 0))
+    }
+
+    // ---------------------------------------------------------------------------------------------
+
+    // Globals are just variables which are assigned before the script starts.
+    fn define_global(&mut self, name: &str, value: &GlobalValue) {
+        let value = match value {
+            GlobalValue::Int(int_type, i) => CodeValue::Int(
+                *int_type,
+                self.fn_builder.ins().iconst(int_cl_type(*int_type), *i),
+            ),
+            GlobalValue::Str(str_val) => {
+                let data_id = self.imm_string(format!("{}\0", str_val).as_bytes());
+                CodeValue::Str(self.data_address(data_id))
+            }
+        };
+        self.assign_variable(name, &value);
+    }
+
+    fn assign_variable(&mut self, name: &str, value: &CodeValue) {
+        let slot = match self.var_map.get(name) {
+            Some(slot) => slot.clone(),
+            None => {
+                let slot = self.declare_slot(value);
+                self.var_map.insert(name.to_string(), slot.clone());
+                slot
+            }
+        };
+        self.def_slot(name, &slot, value);
+    }
+
+    fn declare_slot(&mut self, value: &CodeValue) -> VarSlot {
+        match value {
+            CodeValue::Int(int_type, _) => {
+                let var = Variable::new(self.var_id);
+                self.var_id += 1;
+                self.fn_builder.declare_var(var, int_cl_type(*int_type));
+                VarSlot::Int(*int_type, var)
+            }
+            CodeValue::Str(_) => {
+                let var = Variable::new(self.var_id);
+                self.var_id += 1;
+                self.fn_builder.declare_var(var, self.word_type());
+                VarSlot::Str(var)
+            }
+            CodeValue::BigInt(_) => {
+                let var = Variable::new(self.var_id);
+                self.var_id += 1;
+                self.fn_builder.declare_var(var, self.word_type());
+                VarSlot::BigInt(var)
+            }
+            CodeValue::StrArray(_) => {
+                let var = Variable::new(self.var_id);
+                self.var_id += 1;
+                self.fn_builder.declare_var(var, self.word_type());
+                VarSlot::StrArray(var)
+            }
+            CodeValue::Closure(_, closure_type) => {
+                let var = Variable::new(self.var_id);
+                self.var_id += 1;
+                self.fn_builder.declare_var(var, self.word_type());
+                VarSlot::Closure(var, closure_type.clone())
+            }
+            CodeValue::Tuple(elems) => {
+                VarSlot::Tuple(elems.iter().map(|elem| self.declare_slot(elem)).collect())
+            }
+        }
+    }
+
+    fn def_slot(&mut self, name: &str, slot: &VarSlot, value: &CodeValue) {
+        match (slot, value) {
+            (VarSlot::Int(var_type, var), CodeValue::Int(value_type, value))
+                if var_type == value_type =>
+            {
+                self.fn_builder.def_var(*var, *value)
+            }
+            (VarSlot::Str(var), CodeValue::Str(value))
+            | (VarSlot::BigInt(var), CodeValue::BigInt(value))
+            | (VarSlot::StrArray(var), CodeValue::StrArray(value)) => {
+                self.fn_builder.def_var(*var, *value)
+            }
+            (VarSlot::Closure(var, var_type), CodeValue::Closure(value, value_type))
+                if var_type == value_type =>
+            {
+                self.fn_builder.def_var(*var, *value)
+            }
+            (VarSlot::Tuple(slots), CodeValue::Tuple(elems)) if slots.len() == elems.len() => {
+                for (slot, elem) in slots.iter().zip(elems) {
+                    self.def_slot(name, slot, elem);
+                }
+            }
+            _ => panic!(
+                "Cannot assign {:?} to variable '{}' of a different shape.",
+                value, name
+            ),
+        }
+    }
+
+    fn use_slot(&mut self, slot: &VarSlot) -> CodeValue {
+        match slot {
+            VarSlot::Int(int_type, var) => CodeValue::Int(*int_type, self.fn_builder.use_var(*var)),
+            VarSlot::Str(var) => CodeValue::Str(self.fn_builder.use_var(*var)),
+            VarSlot::BigInt(var) => CodeValue::BigInt(self.fn_builder.use_var(*var)),
+            VarSlot::StrArray(var) => CodeValue::StrArray(self.fn_builder.use_var(*var)),
+            VarSlot::Closure(var, closure_type) => {
+                CodeValue::Closure(self.fn_builder.use_var(*var), closure_type.clone())
+            }
+            VarSlot::Tuple(slots) => {
+                CodeValue::Tuple(slots.iter().map(|slot| self.use_slot(slot)).collect())
+            }
+        }
+    }
+
+    // ---------------------------------------------------------------------------------------------
+    // Closures are passed around as a pointer to their environment record, which is a heap
+    // allocated array of words.  The first word is the address of the compiled function and the
+    // rest hold copies of the captured variables.  The function itself takes the environment
+    // pointer followed by its arguments, all as words, and returns a single word.  Parameters and
+    // return values are `i64` so smaller ints are extended to fit.
+
+    fn word_type(&self) -> Type {
+        self.module.target_config().pointer_type()
+    }
+
+    fn word_bytes(&self) -> i32 {
+        self.word_type().bytes() as i32
+    }
+
+    fn word_const(&mut self, value: i64) -> Value {
+        let word_type = self.word_type();
+        self.fn_builder.ins().iconst(word_type, value)
+    }
+
+    fn value_to_word(&mut self, value: &CodeValue) -> Value {
+        match value {
+            CodeValue::Int(int_type, value) => self.convert_int(*value, *int_type, IntType::I64),
+            CodeValue::Str(value)
+            | CodeValue::BigInt(value)
+            | CodeValue::StrArray(value)
+            | CodeValue::Closure(value, _) => *value,
+            CodeValue::Tuple(_) => panic!("Tuples cannot be passed to or returned from closures."),
+        }
+    }
+
+    fn compile_closure(&mut self, params: &[String], body: &[AstNode]) -> CodeValue {
+        // Any name used in the body which is a variable in this scope (and isn't shadowed by a
+        // parameter) is captured.
+        let mut used = HashSet::new();
+        collect_names_list(body, &mut used);
+        let mut captures = used
+            .iter()
+            .filter(|name| !params.contains(name))
+            .filter_map(|name| {
+                self.var_map
+                    .get(name)
+                    .map(|slot| (name.clone(), slot.clone()))
+            })
+            .collect::<Vec<_>>();
+        captures.sort_by(|a, b| a.0.cmp(&b.0));
+
+        // Which parameters are closures, and whether a closure is returned, is inferred from the
+        // body and the variables in scope.
+        let scope = self
+            .var_map
+            .iter()
+            .filter_map(|(name, slot)| match slot {
+                VarSlot::Int(..) => Some((name.clone(), WordKind::Int)),
+                VarSlot::Closure(_, closure_type) => {
+                    Some((name.clone(), WordKind::Closure(closure_type.clone())))
+                }
+                _ => None,
+            })
+            .collect();
+        let closure_type = Rc::new(kinds::infer_closure_type(params, body, &scope));
+
+        let word_type = self.word_type();
+        let mut sig = self.module.make_signature();
+        sig.params.push(AbiParam::new(word_type));
+        for _ in params {
+            sig.params.push(AbiParam::new(word_type));
+        }
+        sig.returns.push(AbiParam::new(word_type));
+
+        let func_id = self
+            .module
+            .declare_anonymous_function(&sig)
+            .expect("Failed to declare closure.");
+
+        // Compile the closure body as its own function, with its own variables.
+        let mut ctx = self.module.make_context();
+        ctx.func.signature = sig;
+        let mut fn_ctx = FunctionBuilderContext::new();
+        {
+            let mut fn_builder = FunctionBuilder::new(&mut ctx.func, &mut fn_ctx);
+            let block = fn_builder.create_block();
+            fn_builder.append_block_params_for_function_params(block);
+            fn_builder.switch_to_block(block);
+            fn_builder.seal_block(block);
+            let block_params = fn_builder.block_params(block).to_vec();
+
+            let mut closure = Compiler {
+                module: &mut *self.module,
+                fn_builder,
+                data_map: self.data_map.clone(),
+                var_map: HashMap::new(),
+                var_id: 0,
+                in_closure: true,
+                ret_kind: closure_type.ret.clone(),
+                overflow: self.overflow,
+                padded_ints: self.padded_ints,
+                allow_env: self.allow_env,
+                line: self.line,
+                file: self.file.clone(),
+                exc_state: self.exc_state,
+                try_scopes: Vec::new(),
+                unwind_block: None,
+            };
+
+            // Unpack the captured variables from the environment.
+            let env = block_params[0];
+            let mut offset = closure.word_bytes();
+            for (name, slot) in &captures {
+                let value = closure.load_env_value(slot, env, &mut offset);
+                closure.assign_variable(name, &value);
+            }
+
+            for ((param, kind), word) in params
+                .iter()
+                .zip(&closure_type.params)
+                .zip(&block_params[1..])
+            {
+                closure.assign_variable(param, &word_to_value(kind, *word));
+            }
+
+            for stmt in body {
+                closure.compile_code(stmt);
+            }
+
+            // Falling off the end of the closure returns 0.
+            let zero = closure.fn_builder.ins().iconst(word_type, 0);
+            closure.fn_builder.ins().return_(&[zero]);
+            closure.compile_unwind_block();
+            closure.fn_builder.seal_all_blocks();
+            closure.fn_builder.finalize();
+        }
+        self.module
+            .define_function(func_id, &mut ctx)
+            .expect("Failed to define closure.");
+
+        // Build the environment record for this instance of the closure.
+        let mut env_words = 1;
+        for (_, slot) in &captures {
+            env_words += slot_word_count(slot);
+        }
+        let env = self.compile_alloc(env_words * self.word_bytes() as i64);
+
+        let func_ref = self
+            .module
+            .declare_func_in_func(func_id, self.fn_builder.func);
+        let func_addr = self.fn_builder.ins().func_addr(word_type, func_ref);
+        self.fn_builder
+            .ins()
+            .store(MemFlags::trusted(), func_addr, env, 0);
+
+        let mut offset = self.word_bytes();
+        for (_, slot) in &captures {
+            let value = self.use_slot(slot);
+            self.store_env_value(&value, env, &mut offset);
+        }
+
+        CodeValue::Closure(env, Some(closure_type))
+    }
+
+    fn store_env_value(&mut self, value: &CodeValue, env: Value, offset: &mut i32) {
+        match value {
+            CodeValue::Tuple(elems) => {
+                for elem in elems {
+                    self.store_env_value(elem, env, offset);
+                }
+            }
+            _ => {
+                let word = self.value_to_word(value);
+                self.fn_builder
+                    .ins()
+                    .store(MemFlags::trusted(), word, env, *offset);
+                *offset += self.word_bytes();
+            }
+        }
+    }
+
+    fn load_env_value(&mut self, slot: &VarSlot, env: Value, offset: &mut i32) -> CodeValue {
+        let word_type = self.word_type();
+        let load_word = |compiler: &mut Self, offset: &mut i32| {
+            let word = compiler
+                .fn_builder
+                .ins()
+                .load(word_type, MemFlags::trusted(), env, *offset);
+            *offset += compiler.word_bytes();
+            word
+        };
+        match slot {
+            VarSlot::Int(int_type, _) => {
+                let word = load_word(self, offset);
+                CodeValue::Int(*int_type, self.convert_int(word, IntType::I64, *int_type))
+            }
+            VarSlot::Str(_) => CodeValue::Str(load_word(self, offset)),
+            VarSlot::BigInt(_) => CodeValue::BigInt(load_word(self, offset)),
+            VarSlot::StrArray(_) => CodeValue::StrArray(load_word(self, offset)),
+            VarSlot::Closure(_, closure_type) => {
+                CodeValue::Closure(load_word(self, offset), closure_type.clone())
+            }
+            VarSlot::Tuple(slots) => CodeValue::Tuple(
+                slots
+                    .iter()
+                    .map(|slot| self.load_env_value(slot, env, offset))
+                    .collect(),
+            ),
+        }
+    }
+
+    // A closure of unknown type, i.e., one passed in as a parameter, may be passed ints or
+    // closures and is assumed to return an int.
+    fn compile_closure_call(
+        &mut self,
+        env: Value,
+        closure_type: Option<Rc<ClosureType>>,
+        args: &[AstNode],
+    ) -> CodeValue {
+        if let Some(closure_type) = &closure_type {
+            if closure_type.params.len() != args.len() {
+                panic!(
+                    "Closure takes {} argument{}, not {}.",
+                    closure_type.params.len(),
+                    if closure_type.params.len() == 1 {
+                        ""
+                    } else {
+                        "s"
+                    },
+                    args.len()
+                );
+            }
+        }
+
+        let word_type = self.word_type();
+        let mut call_args = vec![env];
+        for (idx, arg) in args.iter().enumerate() {
+            // Parameters are words, either ints or closures, so nothing else may be passed.
+            let value = self.compile_code(arg);
+            let param_kind = closure_type
+                .as_ref()
+                .map(|closure_type| &closure_type.params[idx]);
+            match (&value, param_kind) {
+                (CodeValue::Int(..), None | Some(WordKind::Int)) => (),
+                (CodeValue::Closure(..), None | Some(WordKind::Closure(_))) => (),
+                (CodeValue::Int(..), Some(_)) => {
+                    panic!(
+                        "Closure argument {} must be a closure, found an int.",
+                        idx + 1
+                    )
+                }
+                (CodeValue::Closure(..), Some(_)) => {
+                    panic!(
+                        "Closure argument {} must be an int, found a closure.",
+                        idx + 1
+                    )
+                }
+                (CodeValue::Str(_), _) => panic!("Strings cannot be passed to closures."),
+                (CodeValue::BigInt(_), _) => panic!("Bigints cannot be passed to closures."),
+                _ => panic!(
+                    "Only ints and closures may be passed to closures, found {:?}.",
+                    value
+                ),
+            }
+            call_args.push(self.value_to_word(&value));
+        }
+
+        // A closure which should have returned a closure may have fallen off its end instead.
+        let is_null = self.fn_builder.ins().icmp_imm(IntCC::Equal, env, 0);
+        self.compile_runtime_check(is_null, "call of a null closure");
+
+        let mut sig = self.module.make_signature();
+        for _ in &call_args {
+            sig.params.push(AbiParam::new(word_type));
+        }
+        sig.returns.push(AbiParam::new(word_type));
+        let sig_ref = self.fn_builder.import_signature(sig);
+
+        let func_addr = self
+            .fn_builder
+            .ins()
+            .load(word_type, MemFlags::trusted(), env, 0);
+        let call = self
+            .fn_builder
+            .ins()
+            .call_indirect(sig_ref, func_addr, &call_args);
+        let result = self.fn_builder.inst_results(call)[0];
+
+        // If the closure threw an exception then pass it on to our handler, or if it halted then
+        // halt too.
+        let exc_state = self.data_address(self.exc_state);
+        let pending = self.fn_builder.ins().load(
+            word_type,
+            MemFlags::trusted(),
+            exc_state,
+            EXC_PENDING_OFFSET,
+        );
+        let pending_block = self.fn_builder.create_block();
+        let ok_block = self.fn_builder.create_block();
+        self.fn_builder
+            .ins()
+            .brif(pending, pending_block, &[], ok_block, &[]);
+
+        self.fn_builder.switch_to_block(pending_block);
+        self.fn_builder.seal_block(pending_block);
+        let halted = self
+            .fn_builder
+            .ins()
+            .icmp_imm(IntCC::Equal, pending, EXC_HALTED);
+        let handler_block = self.handler_block();
+        let halt_block = self.fn_builder.create_block();
+        self.fn_builder
+            .ins()
+            .brif(halted, halt_block, &[], handler_block, &[]);
+        self.fn_builder.switch_to_block(halt_block);
+        self.fn_builder.seal_block(halt_block);
+        self.compile_halt();
+
+        self.fn_builder.switch_to_block(ok_block);
+        self.fn_builder.seal_block(ok_block);
+
+        let ret_kind = closure_type.map_or(WordKind::Int, |closure_type| closure_type.ret.clone());
+        word_to_value(&ret_kind, result)
+    }
+
+    // Returning from the top level sets the exit status.
+    fn compile_return(&mut self, expr: &AstNode) -> CodeValue {
+        let value = self.compile_code(expr);
+        match (&self.ret_kind, &value) {
+            (WordKind::Int, CodeValue::Int(..)) => (),
+            (WordKind::Closure(ret_type), CodeValue::Closure(_, value_type))
+                if ret_type.is_none() || ret_type == value_type => {}
+            (WordKind::Int, _) => panic!("Only integers may be returned, found {:?}.", value),
+            (WordKind::Closure(_), _) => {
+                panic!("Expecting a closure to be returned, found {:?}.", value)
+            }
+        }
+        let word = self.value_to_word(&value);
+
+        // Run the `finally` bodies of any enclosing `try` statements first, from the inside out.
+        // Each is compiled as though it was outside of its own `try`.
+        let try_scopes = self.try_scopes.clone();
+        for (idx, scope) in try_scopes.iter().enumerate().rev() {
+            if let Some(finally_body) = &scope.finally {
+                self.try_scopes.truncate(idx);
+                for stmt in finally_body {
+                    self.compile_code(stmt);
+                }
+            }
+        }
+        self.try_scopes = try_scopes;
+
+        self.fn_builder.ins().return_(&[word]);
+
+        // Anything following the return is unreachable, but still needs a block to go in.
+        let dead_block = self.fn_builder.create_block();
+        self.fn_builder.switch_to_block(dead_block);
+        self.fn_builder.seal_block(dead_block);
+        value
+    }
+
+    fn compile_alloc(&mut self, size: i64) -> Value {
+        let size_val = self.word_const(size);
+        self.call_runtime("fbl_alloc", &[size_val])
+    }
+
+    // ---------------------------------------------------------------------------------------------
+
+    fn compile_call(&mut self, name: &str, args: &[AstNode]) -> CodeValue {
+        if let Some(slot) = self.var_map.get(name).cloned() {
+            // Calling a variable, which must hold a closure.
+            match self.use_slot(&slot) {
+                CodeValue::Closure(env, closure_type) => {
+                    self.compile_closure_call(env, closure_type, args)
+                }
+                _ => panic!("Cannot call non-closure variable '{}'.", name),
+            }
+        } else if (name == "print" && args.len() != 1) || is_output_builtin(name) {
+            // Multiple values are printed on one line, separated by spaces.  `write` and `ewrite`
+            // don't add a newline, and `eprint` and `ewrite` print to stderr.
+            let output = OutputTarget {
+                to_stderr: name.starts_with('e'),
+                newline: name.ends_with("print"),
+            };
+            let mut pieces = Vec::new();
+            for idx in 0..args.len() {
+                if idx > 0 {
+                    pieces.push(FormatPiece::Text(" ".to_string()));
+                }
+                pieces.push(FormatPiece::Arg(FormatSpec::default()));
+            }
+            self.compile_format(&pieces, args, output);
+            self.null_value()
+        } else if let Some(arg_count) = builtin_arg_count(MATH_BUILTINS, name) {
+            check_arg_count(name, args, arg_count);
+            self.compile_math(name, args)
+        } else if let Some(arg_count) = builtin_arg_count(STRING_BUILTINS, name) {
+            check_arg_count(name, args, arg_count);
+            self.compile_string_builtin(name, args)
+        } else if name == "printf" {
+            // printf("{:>3} {}", x, y) prints its arguments with a format string which must be a
+            // literal, so it can be checked here.
+            let fmt = match args.first() {
+                Some(AstNode::Literal(AstValue::Text(fmt))) => {
+                    String::from_utf8_lossy(&fmt[..fmt.len() - 1]).into_owned()
+                }
+                _ => panic!("printf() requires a string literal format."),
+            };
+            let pieces = format::parse_format(&fmt)
+                .unwrap_or_else(|msg| panic!("Bad printf() format: {}.", msg));
+            let output = OutputTarget {
+                to_stderr: false,
+                newline: true,
+            };
+            self.compile_format(&pieces, &args[1..], output);
+            self.null_value()
+        } else if name == "print" {
+            // A single value is printed by itself.
+            match &args[0] {
+                AstNode::Literal(AstValue::Text(s)) => self.compile_print_str(s),
+                AstNode::Literal(AstValue::Int(_)) => self.compile_print_int(&args[0]),
+                expr => {
+                    let value = self.compile_code(expr);
+                    self.compile_print_value(&value)
+                }
+            };
+            self.null_value()
+        } else if name == "abort" {
+            // abort() or abort("message") stops the script with a runtime error.
+            let msg = match args {
+                [] => "abort() called".to_string(),
+                [AstNode::Literal(AstValue::Text(s))] => {
+                    String::from_utf8_lossy(&s[..s.len() - 1]).into_owned()
+                }
+                _ => panic!("abort() takes an optional string literal message."),
+            };
+            self.compile_fatal_error(&msg);
+
+            // Anything following the abort is unreachable, but still needs a block to go in.
+            let dead_block = self.fn_builder.create_block();
+            self.fn_builder.switch_to_block(dead_block);
+            self.fn_builder.seal_block(dead_block);
+            self.null_value()
+        } else if name == "args" {
+            // args() is the array of arguments given to the script, after `--`.
+            assert!(args.is_empty());
+            CodeValue::StrArray(self.call_runtime("fbl_args", &[]))
+        } else if name == "argc" {
+            // argc() is the number of arguments given to the script.
+            assert!(args.is_empty());
+            CodeValue::Int(IntType::I64, self.call_runtime("fbl_argc", &[]))
+        } else if name == "exit" {
+            // exit(code) flushes the output and ends the script immediately with the status `code`.
+            assert!(args.len() == 1);
+            let (int_type, status) = self.compile_typed_int(&args[0]);
+            let status = self.convert_int(status, int_type, IntType::I64);
+            self.call_runtime_exit("fbl_exit", &[status]);
+
+            // Anything following the exit is unreachable, but still needs a block to go in.
+            let dead_block = self.fn_builder.create_block();
+            self.fn_builder.switch_to_block(dead_block);
+            self.fn_builder.seal_block(dead_block);
+            self.null_value()
+        } else if name == "read_line" {
+            // read_line() returns the next line from stdin, or null at the end of the input.
+            assert!(args.is_empty());
+            CodeValue::Str(self.call_runtime("fbl_read_line", &[]))
+        } else if name == "read_int" {
+            // read_int() reads a line from stdin and parses it as an int, throwing an error if it
+            // can't.
+            assert!(args.is_empty());
+            CodeValue::Int(
+                IntType::I64,
+                self.call_runtime_fallible("fbl_read_int", &[]),
+            )
+        } else if name == "getenv" || name == "has_env" {
+            // getenv(name) returns the value of an environment variable, or null if it isn't set.
+            // has_env(name) checks whether it's set.  Both throw if environment access is
+            // disabled.
+            assert!(args.len() == 1);
+            let name_val = self.compile_str(&args[0]);
+            if !self.allow_env {
+                self.compile_throw_str(&format!(
+                    "{}() is not allowed, environment access is disabled",
+                    name
+                ));
+            }
+            if name == "getenv" {
+                CodeValue::Str(self.call_runtime("fbl_getenv", &[name_val]))
+            } else {
+                CodeValue::Int(IntType::I64, self.call_runtime("fbl_has_env", &[name_val]))
+            }
+        } else if name == "open" {
+            // open(path, mode) opens a file to read ("r"), write ("w") or append ("a") and returns
+            // its handle.
+            assert!(args.len() == 2);
+            let path = self.compile_str(&args[0]);
+            let mode = self.compile_str(&args[1]);
+            CodeValue::Int(
+                IntType::I64,
+                self.call_runtime_fallible("fbl_file_open", &[path, mode]),
+            )
+        } else if name == "close" {
+            // close(f) closes the file with handle `f`.
+            assert!(args.len() == 1);
+            let handle = self.compile_int_as(&args[0], IntType::I64);
+            self.call_runtime_fallible("fbl_file_close", &[handle]);
+            self.null_value()
+        } else if name == "read_all" || name == "read_lines" {
+            // read_all(f) returns the rest of a file as a string, and read_lines(f) as an array of
+            // its lines.  `f` is either a handle from open() or a path.
+            assert!(args.len() == 1);
+            let (file, by_path) = self.compile_file_arg(&args[0]);
+            let func_name = match (name, by_path) {
+                ("read_all", false) => "fbl_file_read_all",
+                ("read_all", true) => "fbl_path_read_all",
+                (_, false) => "fbl_file_read_lines",
+                (_, true) => "fbl_path_read_lines",
+            };
+            let result = self.call_runtime_fallible(func_name, &[file]);
+            if name == "read_all" {
+                CodeValue::Str(result)
+            } else {
+                CodeValue::StrArray(result)
+            }
+        } else if name == "write_file" || name == "append_file" {
+            // write_file(f, s) and append_file(f, s) write the string `s` to a file, either a
+            // handle from open() or a path.  Writing to a path replaces the whole file.
+            assert!(args.len() == 2);
+            let (file, by_path) = self.compile_file_arg(&args[0]);
+            let text = self.compile_str(&args[1]);
+            let append = self.word_const((name == "append_file") as i64);
+            let func_name = if by_path {
+                "fbl_path_write"
+            } else {
+                "fbl_file_write"
+            };
+            self.call_runtime_fallible(func_name, &[file, text, append]);
+            self.null_value()
+        } else if name == "is_null" {
+            // is_null(s) checks for a null string, e.g., from read_line() at the end of the input or
+            // getenv() for an unset variable.
+            assert!(args.len() == 1);
+            let str_val = self.compile_str(&args[0]);
+            let is_null = self.fn_builder.ins().icmp_imm(IntCC::Equal, str_val, 0);
+            self.bool_to_int(is_null)
+        } else if name == "flush" {
+            // flush() writes out any buffered output.
+            assert!(args.is_empty());
+            self.call_runtime("fbl_flush", &[]);
+            self.null_value()
+        } else if name == "divmod" {
+            // divmod(x, y) returns the tuple (x / y, x % y).
+            assert!(args.len() == 2);
+            let (int_type, lhs, rhs) = self.compile_int_operands(&args[0], &args[1]);
+            let quot = self.compile_arith("/", int_type, lhs, rhs);
+            let rem = self.compile_arith("%", int_type, lhs, rhs);
+            CodeValue::Tuple(vec![
+                CodeValue::Int(int_type, quot),
+                CodeValue::Int(int_type, rem),
+            ])
+        } else if name == "&&" {
+            // Cranelift doesn't seem to have a logical and operator, nor a way to easily convert a
+            // wider value to a bool (b1).  (I hoped breduce did this, but it seems no.)  Until we
+            // discover a better way, we can use icmp to convert to bool.  The operands needn't be
+            // the same type.
+            assert!(args.len() == 2);
+            let lhs = self.compile_int(&args[0]);
+            let rhs = self.compile_int(&args[1]);
+            let lhs_bool = self.fn_builder.ins().icmp_imm(IntCC::NotEqual, lhs, 0);
+            let rhs_bool = self.fn_builder.ins().icmp_imm(IntCC::NotEqual, rhs, 0);
+            let res_bool = self.fn_builder.ins().band(lhs_bool, rhs_bool);
+            self.bool_to_int(res_bool)
+        } else if name == "~" {
+            assert!(args.len() == 1);
+            let (int_type, value) = self.compile_typed_int(&args[0]);
+            CodeValue::Int(int_type, self.fn_builder.ins().bnot(value))
+        } else if name == "<<" || name == ">>" || name == ">>>" {
+            // The shift amount may be any int type.  `>>` is arithmetic and `>>>` is logical,
+            // regardless of the signedness of the value being shifted.
+            assert!(args.len() == 2);
+            let (int_type, lhs) = self.compile_typed_int(&args[0]);
+            let (amount_type, amount) = self.compile_typed_int(&args[1]);
+            let amount = self.convert_int(amount, amount_type, IntType::I64);
+            CodeValue::Int(int_type, self.compile_shift(name, int_type, lhs, amount))
+        } else {
+            // Otherwise it's one of the binary operators.
+            assert!(args.len() == 2);
+            let (int_type, lhs, rhs) = match self.compile_operands(&args[0], &args[1]) {
+                Operands::Int(int_type, lhs, rhs) => (int_type, lhs, rhs),
+                Operands::BigInt(lhs, rhs) => return self.compile_bigint_op(name, lhs, rhs),
+            };
+            let signed = int_type.is_signed();
+            let cmp_cc = match name {
+                "==" => Some(IntCC::Equal),
+                "!=" => Some(IntCC::NotEqual),
+                "<" if signed => Some(IntCC::SignedLessThan),
+                "<" => Some(IntCC::UnsignedLessThan),
+                "<=" if signed => Some(IntCC::SignedLessThanOrEqual),
+                "<=" => Some(IntCC::UnsignedLessThanOrEqual),
+                ">" if signed => Some(IntCC::SignedGreaterThan),
+                ">" => Some(IntCC::UnsignedGreaterThan),
+                ">=" if signed => Some(IntCC::SignedGreaterThanOrEqual),
+                ">=" => Some(IntCC::UnsignedGreaterThanOrEqual),
+                _ => None,
+            };
+            if let Some(cc) = cmp_cc {
+                let cmp_val = self.fn_builder.ins().icmp(cc, lhs, rhs);
+                return self.bool_to_int(cmp_val);
+            }
+
+            match name {
+                "&" => return CodeValue::Int(int_type, self.fn_builder.ins().band(lhs, rhs)),
+                "|" => return CodeValue::Int(int_type, self.fn_builder.ins().bor(lhs, rhs)),
+                "^" => return CodeValue::Int(int_type, self.fn_builder.ins().bxor(lhs, rhs)),
+                _ => (),
+            }
+
+            CodeValue::Int(int_type, self.compile_arith(name, int_type, lhs, rhs))
+        }
+    }
+
+    fn compile_arith(&mut self, op: &str, int_type: IntType, lhs: Value, rhs: Value) -> Value {
+        let signed = int_type.is_signed();
+        let const_rhs = self.const_int_value(rhs);
+        if (op == "/" || op == "%") && const_rhs.is_none_or(|c| c == 0) {
+            // Cranelift would trap on a zero divisor, so check for it first.
+            let is_zero = self.fn_builder.ins().icmp_imm(IntCC::Equal, rhs, 0);
+            let msg = format!("division by zero in `{}`", op);
+            self.compile_runtime_check(is_zero, &msg);
+        }
+
+        if op == "%" {
+            // Remainder can't overflow.  (Cranelift defines `MIN % -1` as 0.)
+            return if signed {
+                self.fn_builder.ins().srem(lhs, rhs)
+            } else {
+                self.fn_builder.ins().urem(lhs, rhs)
+            };
+        }
+
+        if op == "/" {
+            if !signed || const_rhs.is_some_and(|c| c != -1) {
+                return if signed {
+                    self.fn_builder.ins().sdiv(lhs, rhs)
+                } else {
+                    self.fn_builder.ins().udiv(lhs, rhs)
+                };
+            }
+
+            // Signed division only overflows for `MIN / -1`, which Cranelift would trap on
+            // regardless of the mode, so we need to check for it ourselves.
+            let int_cl_type = int_cl_type(int_type);
+            let lhs_is_min =
+                self.fn_builder
+                    .ins()
+                    .icmp_imm(IntCC::Equal, lhs, int_type.min_value() as i64);
+            let rhs_is_neg_one = self.fn_builder.ins().icmp_imm(IntCC::Equal, rhs, -1);
+            let overflowed = self.fn_builder.ins().band(lhs_is_min, rhs_is_neg_one);
+            if self.overflow == OverflowMode::Trap {
+                self.compile_overflow_check(op, overflowed);
+                return self.fn_builder.ins().sdiv(lhs, rhs);
+            }
+            let one = self.fn_builder.ins().iconst(int_cl_type, 1);
+            let safe_rhs = self.fn_builder.ins().select(overflowed, one, rhs);
+            let quot = self.fn_builder.ins().sdiv(lhs, safe_rhs);
+            let overflow_val = match self.overflow {
+                OverflowMode::Saturate => int_type.max_value() as i64,
+                _ => int_type.min_value() as i64,
+            };
+            let overflow_val = self.fn_builder.ins().iconst(int_cl_type, overflow_val);
+            return self.fn_builder.ins().select(overflowed, overflow_val, quot);
+        }
+
+        if self.overflow == OverflowMode::Wrap {
+            return match op {
+                "+" => self.fn_builder.ins().iadd(lhs, rhs),
+                "-" => self.fn_builder.ins().isub(lhs, rhs),
+                "*" => self.fn_builder.ins().imul(lhs, rhs),
+
+                _ => panic!("Unexpected function call: '{}'", op),
+            };
+        }
+
+        let (result, overflowed) = match (op, signed) {
+            ("+", true) => self.fn_builder.ins().sadd_overflow(lhs, rhs),
+            ("+", false) => self.fn_builder.ins().uadd_overflow(lhs, rhs),
+            ("-", true) => self.fn_builder.ins().ssub_overflow(lhs, rhs),
+            ("-", false) => self.fn_builder.ins().usub_overflow(lhs, rhs),
+            ("*", true) => self.fn_builder.ins().smul_overflow(lhs, rhs),
+            ("*", false) => self.fn_builder.ins().umul_overflow(lhs, rhs),
+
+            _ => panic!("Unexpected function call: '{}'", op),
+        };
+        if self.overflow == OverflowMode::Trap {
+            self.compile_overflow_check(op, overflowed);
+            return result;
+        }
+
+        // Saturate towards the limit in the direction of the overflow.  For signed add and
+        // subtract that's the direction of the LHS, and for multiply it depends on whether the
+        // signs of the operands differ.  Unsigned can only overflow upwards, except subtract.
+        let int_cl_type = int_cl_type(int_type);
+        let min_val = self
+            .fn_builder
+            .ins()
+            .iconst(int_cl_type, int_type.min_value() as i64);
+        let max_val = self
+            .fn_builder
+            .ins()
+            .iconst(int_cl_type, int_type.max_value() as i64);
+        let limit = match (op, signed) {
+            ("-", false) => min_val,
+            (_, false) => max_val,
+            ("*", true) => {
+                let signs = self.fn_builder.ins().bxor(lhs, rhs);
+                let is_neg = self
+                    .fn_builder
+                    .ins()
+                    .icmp_imm(IntCC::SignedLessThan, signs, 0);
+                self.fn_builder.ins().select(is_neg, min_val, max_val)
+            }
+            _ => {
+                let is_neg = self
+                    .fn_builder
+                    .ins()
+                    .icmp_imm(IntCC::SignedLessThan, lhs, 0);
+                self.fn_builder.ins().select(is_neg, min_val, max_val)
+            }
+        };
+        self.fn_builder.ins().select(overflowed, limit, result)
+    }
+
+    // ---------------------------------------------------------------------------------------------
+    // Bigints are implemented in the runtime library.  Ints are converted to bigints implicitly
+    // when needed since it's always lossless.
+
+    fn compile_bigint(&mut self, expr: &AstNode) -> Value {
+        match self.compile_code(expr) {
+            CodeValue::BigInt(value) => value,
+            CodeValue::Int(int_type, value) => {
+                let value = self.convert_int(value, int_type, IntType::I64);
+                if int_type == IntType::U64 {
+                    self.call_runtime("fbl_bigint_from_u64", &[value])
+                } else {
+                    self.call_runtime("fbl_bigint_from_i64", &[value])
+                }
+            }
+            value => panic!("Expecting a bigint, found {:?}.", value),
+        }
+    }
+
+    fn compile_bigint_op(&mut self, op: &str, lhs: Value, rhs: Value) -> CodeValue {
+        let cmp_cc = match op {
+            "==" => Some(IntCC::Equal),
+            "!=" => Some(IntCC::NotEqual),
+            "<" => Some(IntCC::SignedLessThan),
+            "<=" => Some(IntCC::SignedLessThanOrEqual),
+            ">" => Some(IntCC::SignedGreaterThan),
+            ">=" => Some(IntCC::SignedGreaterThanOrEqual),
+            _ => None,
+        };
+        if let Some(cc) = cmp_cc {
+            let ordering = self.call_runtime("fbl_bigint_cmp", &[lhs, rhs]);
+            let cmp_val = self.fn_builder.ins().icmp_imm(cc, ordering, 0);
+            return self.bool_to_int(cmp_val);
+        }
+
+        let func_name = match op {
+            "+" => "fbl_bigint_add",
+            "-" => "fbl_bigint_sub",
+            "*" => "fbl_bigint_mul",
+            "/" => "fbl_bigint_div",
+            "%" => "fbl_bigint_rem",
+            _ => panic!("Operator `{}` is not supported for bigints.", op),
+        };
+        if op == "/" || op == "%" {
+            let is_zero = self.call_runtime("fbl_bigint_is_zero", &[rhs]);
+            let msg = format!("division by zero in `{}`", op);
+            self.compile_runtime_check(is_zero, &msg);
+        }
+        CodeValue::BigInt(self.call_runtime(func_name, &[lhs, rhs]))
+    }
+
+    // ---------------------------------------------------------------------------------------------
+    // Strings are stored as UTF-8 but their operations work on characters, i.e., code points.
+
+    fn compile_str(&mut self, expr: &AstNode) -> Value {
+        match self.compile_code(expr) {
+            CodeValue::Str(value) => value,
+            value => panic!("Expecting a string, found {:?}.", value),
+        }
+    }
+
+    // A file argument is either an int handle from `open()` or a string path.  Returns the value
+    // and whether it's a path.
+    fn compile_file_arg(&mut self, arg: &AstNode) -> (Value, bool) {
+        match self.compile_code(arg) {
+            CodeValue::Str(path) => (path, true),
+            CodeValue::Int(int_type, handle) => {
+                (self.convert_int(handle, int_type, IntType::I64), false)
+            }
+            value => panic!("Expecting a file handle or path, found {:?}.", value),
+        }
+    }
+
+    // Indexing a string returns the character at that index as a new string.  Indexing an array
+    // returns the element.
+    fn compile_index(&mut self, expr: &AstNode, idx: &AstNode) -> CodeValue {
+        let str_val = match self.compile_code(expr) {
+            CodeValue::Str(value) => value,
+            CodeValue::StrArray(array) => {
+                let idx_val = self.compile_int_as(idx, IntType::I64);
+                let elem = self.call_runtime_fallible("fbl_array_get", &[array, idx_val]);
+                return CodeValue::Str(elem);
+            }
+            value => panic!("Cannot index {:?}.", value),
+        };
+        let idx_val = self.compile_int_as(idx, IntType::I64);
+        let char_str = self.call_runtime("fbl_str_char_at", &[str_val, idx_val]);
+
+        // The runtime returns null if the index is out of range.
+        let out_of_range = self.fn_builder.ins().icmp_imm(IntCC::Equal, char_str, 0);
+        self.compile_runtime_check(out_of_range, "string index out of range");
+        CodeValue::Str(char_str)
+    }
+
+    // Call a runtime library function.  All of them take and return words.
+    fn call_runtime(&mut self, name: &str, args: &[Value]) -> Value {
+        let word_type = self.word_type();
+        let params = vec![word_type; args.len()];
+        let callee = self.import_function(name, &params, &[word_type]);
+        let call = self.fn_builder.ins().call(callee, args);
+        self.fn_builder.inst_results(call)[0]
+    }
+
+    // Call a runtime library function which may fail, throwing its error if it does.
+    fn call_runtime_fallible(&mut self, name: &str, args: &[Value]) -> Value {
+        let result = self.call_runtime(name, args);
+        let error = self.call_runtime("fbl_take_error", &[]);
+
+        let error_block = self.fn_builder.create_block();
+        let ok_block = self.fn_builder.create_block();
+        self.fn_builder
+            .ins()
+            .brif(error, error_block, &[], ok_block, &[]);
+        self.fn_builder.switch_to_block(error_block);
+        self.fn_builder.seal_block(error_block);
+        self.compile_throw(error);
+
+        self.fn_builder.switch_to_block(ok_block);
+        self.fn_builder.seal_block(ok_block);
+        result
+    }
+
+    // Call a runtime library function which ends the script, recording how it finished, and then
+    // return all the way out of the main function.
+    fn call_runtime_exit(&mut self, name: &str, args: &[Value]) {
+        self.call_runtime(name, args);
+        self.compile_halt();
+    }
+
+    // A closure which halts marks the exception state for its caller, which checks for it along
+    // with pending exceptions and halts too.  Any `finally` bodies are skipped.
+    fn compile_halt(&mut self) {
+        let word_type = self.word_type();
+        if self.in_closure {
+            let exc_state = self.data_address(self.exc_state);
+            let halted = self.fn_builder.ins().iconst(word_type, EXC_HALTED);
+            self.fn_builder
+                .ins()
+                .store(MemFlags::trusted(), halted, exc_state, EXC_PENDING_OFFSET);
+        }
+        let zero = self.fn_builder.ins().iconst(word_type, 0);
+        self.fn_builder.ins().return_(&[zero]);
+    }
+
+    // If `value` is an immediate then return it, so we can avoid unnecessary runtime checks.
+    fn const_int_value(&self, value: Value) -> Option<i64> {
+        let dfg = &self.fn_builder.func.dfg;
+        let inst = dfg.value_def(value).inst()?;
+        match dfg.insts[inst] {
+            InstructionData::UnaryImm {
+                opcode: Opcode::Iconst,
+                imm,
+            } => {
+                // Immediates are stored zero extended, so sign extend them for narrower types.
+                let shift = 64 - dfg.value_type(value).bits();
+                Some((imm.bits() << shift) >> shift)
+            }
+            _ => None,
+        }
+    }
+
+    // Cranelift masks the shift amount to the width of the type, but shifting by the width or
+    // more, or by a negative amount, shifts out every bit instead.  `<<` is multiplication by a
+    // power of two, so it overflows like `*` does, whereas `>>` and `>>>` can't overflow.
+    fn compile_shift(&mut self, op: &str, int_type: IntType, lhs: Value, amount: Value) -> Value {
+        let bits = int_type.bits() as i64;
+        let in_range = self
+            .fn_builder
+            .ins()
+            .icmp_imm(IntCC::UnsignedLessThan, amount, bits);
+        let zero = self.fn_builder.ins().iconst(int_cl_type(int_type), 0);
+        match op {
+            ">>" => {
+                // Shifting by the width less one leaves only copies of the sign bit.
+                let sign_shift = self.fn_builder.ins().iconst(types::I64, bits - 1);
+                let amount = self.fn_builder.ins().select(in_range, amount, sign_shift);
+                self.fn_builder.ins().sshr(lhs, amount)
+            }
+            ">>>" => {
+                let shifted = self.fn_builder.ins().ushr(lhs, amount);
+                self.fn_builder.ins().select(in_range, shifted, zero)
+            }
+            _ => {
+                let shifted = self.fn_builder.ins().ishl(lhs, amount);
+                let result = self.fn_builder.ins().select(in_range, shifted, zero);
+                if self.overflow == OverflowMode::Wrap {
+                    return result;
+                }
+
+                // It overflowed if shifting back doesn't give the original value.
+                let unshifted = if int_type.is_signed() {
+                    self.fn_builder.ins().sshr(result, amount)
+                } else {
+                    self.fn_builder.ins().ushr(result, amount)
+                };
+                let overflowed = self.fn_builder.ins().icmp(IntCC::NotEqual, unshifted, lhs);
+                let int_cl_type = int_cl_type(int_type);
+                let max_val = self
+                    .fn_builder
+                    .ins()
+                    .iconst(int_cl_type, int_type.max_value() as i64);
+                let saturated = if int_type.is_signed() {
+                    let min_val = self
+                        .fn_builder
+                        .ins()
+                        .iconst(int_cl_type, int_type.min_value() as i64);
+                    let is_neg = self
+                        .fn_builder
+                        .ins()
+                        .icmp_imm(IntCC::SignedLessThan, lhs, 0);
+                    self.fn_builder.ins().select(is_neg, min_val, max_val)
+                } else {
+                    max_val
+                };
+                self.compile_overflow_result(op, overflowed, result, saturated)
+            }
+        }
+    }
+
+    fn compile_overflow_check(&mut self, op: &str, overflowed: Value) {
+        let msg = format!("integer overflow in `{}`", op);
+        self.compile_runtime_check(overflowed, &msg);
+    }
+
+    // Handle a possible overflow according to the overflow mode.  `result` is the wrapped value
+    // and `saturated` the value to use when saturating.
+    fn compile_overflow_result(
+        &mut self,
+        op: &str,
+        overflowed: Value,
+        result: Value,
+        saturated: Value,
+    ) -> Value {
+        match self.overflow {
+            OverflowMode::Wrap => result,
+            OverflowMode::Trap => {
+                self.compile_overflow_check(op, overflowed);
+                result
+            }
+            OverflowMode::Saturate => self.fn_builder.ins().select(overflowed, saturated, result),
+        }
+    }
+
+    // ---------------------------------------------------------------------------------------------
+    // The math builtins work on ints of any type, with literals taking on the type of the other
+    // arguments, like the operators.  Those which Cranelift has instructions for are inline, and
+    // the rest call the runtime.  The argument counts have already been checked.
+
+    fn compile_math(&mut self, name: &str, args: &[AstNode]) -> CodeValue {
+        match name {
+            "abs" => {
+                let (int_type, value) = self.compile_typed_int(&args[0]);
+                CodeValue::Int(int_type, self.compile_abs(int_type, value))
+            }
+            "sign" => {
+                // sign(x) is -1, 0 or 1.
+                let (int_type, value) = self.compile_typed_int(&args[0]);
+                let sign = if int_type.is_signed() {
+                    let is_pos = self
+                        .fn_builder
+                        .ins()
+                        .icmp_imm(IntCC::SignedGreaterThan, value, 0);
+                    let is_neg = self
+                        .fn_builder
+                        .ins()
+                        .icmp_imm(IntCC::SignedLessThan, value, 0);
+                    let is_pos = self.bool_to_type(is_pos, int_type);
+                    let is_neg = self.bool_to_type(is_neg, int_type);
+                    self.fn_builder.ins().isub(is_pos, is_neg)
+                } else {
+                    let is_pos = self.fn_builder.ins().icmp_imm(IntCC::NotEqual, value, 0);
+                    self.bool_to_type(is_pos, int_type)
+                };
+                CodeValue::Int(int_type, sign)
+            }
+            "min" | "max" => {
+                let (int_type, lhs, rhs) = self.compile_int_operands(&args[0], &args[1]);
+                CodeValue::Int(int_type, self.compile_min_max(name, int_type, lhs, rhs))
+            }
+            "clamp" => {
+                // clamp(x, lo, hi) is `x` limited to between `lo` and `hi`, which must be in
+                // order.
+                let (int_type, value, lo) = self.compile_int_operands(&args[0], &args[1]);
+                let hi = self.compile_int_as(&args[2], int_type);
+                let cc = if int_type.is_signed() {
+                    IntCC::SignedGreaterThan
+                } else {
+                    IntCC::UnsignedGreaterThan
+                };
+                let reversed = self.fn_builder.ins().icmp(cc, lo, hi);
+                self.compile_runtime_check(
+                    reversed,
+                    "clamp() lower bound is above the upper bound",
+                );
+                let value = self.compile_min_max("max", int_type, value, lo);
+                CodeValue::Int(int_type, self.compile_min_max("min", int_type, value, hi))
+            }
+            "pow" => {
+                let (int_type, base) = self.compile_typed_int(&args[0]);
+                let (exp_type, exp) = if is_untyped_int(&args[1]) {
+                    (IntType::I64, self.compile_int(&args[1]))
+                } else {
+                    self.compile_typed_int(&args[1])
+                };
+                CodeValue::Int(int_type, self.compile_pow(int_type, base, exp_type, exp))
+            }
+            "gcd" => {
+                let (int_type, lhs, rhs) = self.compile_int_operands(&args[0], &args[1]);
+                CodeValue::Int(int_type, self.compile_gcd(int_type, lhs, rhs))
+            }
+            "lcm" => {
+                // lcm(x, y) is |x / gcd(x, y) * y|, or 0 if either is 0.
+                let (int_type, lhs, rhs) = self.compile_int_operands(&args[0], &args[1]);
+                let gcd = self.compile_gcd(int_type, lhs, rhs);
+                let int_cl_type = int_cl_type(int_type);
+                let is_zero = self.fn_builder.ins().icmp_imm(IntCC::Equal, gcd, 0);
+                let one = self.fn_builder.ins().iconst(int_cl_type, 1);
+                let divisor = self.fn_builder.ins().select(is_zero, one, gcd);
+                let quot = if int_type.is_signed() {
+                    self.fn_builder.ins().sdiv(lhs, divisor)
+                } else {
+                    self.fn_builder.ins().udiv(lhs, divisor)
+                };
+                let lcm = self.compile_arith("*", int_type, quot, rhs);
+                CodeValue::Int(int_type, self.compile_abs(int_type, lcm))
+            }
+            "isqrt" => {
+                // isqrt(x) is the floor of the square root of `x`, which mustn't be negative.
+                let (int_type, value) = self.compile_typed_int(&args[0]);
+                let word = self.convert_int(value, int_type, IntType::I64);
+                let signed = self.word_const(int_type.is_signed() as i64);
+                let root = self.call_runtime_fallible("fbl_isqrt", &[word, signed]);
+                CodeValue::Int(int_type, self.convert_int(root, IntType::I64, int_type))
+            }
+            _ => unreachable!("Unknown math builtin: {}", name),
+        }
+    }
+
+    fn compile_abs(&mut self, int_type: IntType, value: Value) -> Value {
+        if !int_type.is_signed() {
+            return value;
+        }
+        // Only MIN overflows, and it wraps to itself.
+        let abs = self.fn_builder.ins().iabs(value);
+        let overflowed =
+            self.fn_builder
+                .ins()
+                .icmp_imm(IntCC::Equal, value, int_type.min_value() as i64);
+        let max_val = self
+            .fn_builder
+            .ins()
+            .iconst(int_cl_type(int_type), int_type.max_value() as i64);
+        self.compile_overflow_result("abs", overflowed, abs, max_val)
+    }
+
+    fn compile_min_max(&mut self, name: &str, int_type: IntType, lhs: Value, rhs: Value) -> Value {
+        match (name, int_type.is_signed()) {
+            ("min", true) => self.fn_builder.ins().smin(lhs, rhs),
+            ("min", false) => self.fn_builder.ins().umin(lhs, rhs),
+            (_, true) => self.fn_builder.ins().smax(lhs, rhs),
+            (_, false) => self.fn_builder.ins().umax(lhs, rhs),
+        }
+    }
+
+    // The runtime finds the gcd of the magnitudes, which only overflows for signed types when
+    // it's 2^(bits - 1), e.g., `gcd(MIN, 0)`.
+    fn compile_gcd(&mut self, int_type: IntType, lhs: Value, rhs: Value) -> Value {
+        let lhs = self.convert_int(lhs, int_type, IntType::I64);
+        let rhs = self.convert_int(rhs, int_type, IntType::I64);
+        let signed = self.word_const(int_type.is_signed() as i64);
+        let gcd = self.call_runtime("fbl_gcd", &[lhs, rhs, signed]);
+        let result = self.convert_int(gcd, IntType::U64, int_type);
+        if !int_type.is_signed() {
+            return result;
+        }
+        let overflowed = self.fn_builder.ins().icmp_imm(
+            IntCC::UnsignedGreaterThan,
+            gcd,
+            int_type.max_value() as i64,
+        );
+        let max_val = self
+            .fn_builder
+            .ins()
+            .iconst(int_cl_type(int_type), int_type.max_value() as i64);
+        self.compile_overflow_result("gcd", overflowed, result, max_val)
+    }
+
+    // ---------------------------------------------------------------------------------------------
+    // The string builtins are all in the runtime.  Strings are indexed by character, like
+    // `s[i]`, and those which make a new string leave the original as it is.  The argument counts
+    // have already been checked.
+
+    fn compile_string_builtin(&mut self, name: &str, args: &[AstNode]) -> CodeValue {
+        match name {
+            "len" => {
+                // len(s) is the number of characters in a string, or elements in an array.
+                let len = match self.compile_code(&args[0]) {
+                    CodeValue::Str(value) => self.call_runtime("fbl_str_len", &[value]),
+                    CodeValue::StrArray(value) => self.call_runtime("fbl_array_len", &[value]),
+                    value => panic!("Cannot take the length of {:?}.", value),
+                };
+                CodeValue::Int(IntType::I64, len)
+            }
+            "to_string" => {
+                // to_string(x) formats an int or bigint.
+                let str_val = match self.compile_code(&args[0]) {
+                    CodeValue::Int(int_type, value) if int_type.is_signed() => {
+                        let value = self.convert_int(value, int_type, IntType::I64);
+                        self.call_runtime("fbl_i64_to_string", &[value])
+                    }
+                    CodeValue::Int(int_type, value) => {
+                        let value = self.convert_int(value, int_type, IntType::U64);
+                        self.call_runtime("fbl_u64_to_string", &[value])
+                    }
+                    CodeValue::BigInt(value) => self.call_runtime("fbl_bigint_to_string", &[value]),
+                    value => panic!("Expecting an integer, found {:?}.", value),
+                };
+                CodeValue::Str(str_val)
+            }
+            "parse_int" => {
+                // parse_int(s) parses a string as an int, throwing an error if it can't.
+                let str_val = self.compile_str(&args[0]);
+                CodeValue::Int(
+                    IntType::I64,
+                    self.call_runtime_fallible("fbl_parse_int", &[str_val]),
+                )
+            }
+            "substr" => {
+                // substr(s, start, count) is the `count` characters from `start`, which must all
+                // be within the string.
+                let str_val = self.compile_str(&args[0]);
+                let start = self.compile_word_int(&args[1]);
+                let count = self.compile_word_int(&args[2]);
+                CodeValue::Str(
+                    self.call_runtime_fallible("fbl_str_substr", &[str_val, start, count]),
+                )
+            }
+            "repeat" => {
+                // repeat(s, n) is `s` repeated `n` times.
+                let str_val = self.compile_str(&args[0]);
+                let count = self.compile_word_int(&args[1]);
+                CodeValue::Str(self.call_runtime_fallible("fbl_str_repeat", &[str_val, count]))
+            }
+            "split" => {
+                // split(s, sep) is the array of the parts of `s` between each `sep`.
+                let str_val = self.compile_str(&args[0]);
+                let sep = self.compile_str(&args[1]);
+                CodeValue::StrArray(self.call_runtime_fallible("fbl_str_split", &[str_val, sep]))
+            }
+            "find" => {
+                // find(s, sub) is the index of the first `sub` in `s`, or -1.
+                let str_val = self.compile_str(&args[0]);
+                let sub = self.compile_str(&args[1]);
+                CodeValue::Int(
+                    IntType::I64,
+                    self.call_runtime("fbl_str_find", &[str_val, sub]),
+                )
+            }
+            "starts_with" => {
+                // starts_with(s, prefix) is 1 if `s` starts with `prefix`, otherwise 0.
+                let str_val = self.compile_str(&args[0]);
+                let prefix = self.compile_str(&args[1]);
+                CodeValue::Int(
+                    IntType::I64,
+                    self.call_runtime("fbl_str_starts_with", &[str_val, prefix]),
+                )
+            }
+            "replace" => {
+                // replace(s, from, to) replaces every `from` in `s` with `to`.
+                let str_val = self.compile_str(&args[0]);
+                let from = self.compile_str(&args[1]);
+                let to = self.compile_str(&args[2]);
+                CodeValue::Str(self.call_runtime("fbl_str_replace", &[str_val, from, to]))
+            }
+            "trim" => {
+                // trim(s) removes leading and trailing whitespace.
+                let str_val = self.compile_str(&args[0]);
+                CodeValue::Str(self.call_runtime("fbl_str_trim", &[str_val]))
+            }
+            "upper" => {
+                let str_val = self.compile_str(&args[0]);
+                CodeValue::Str(self.call_runtime("fbl_str_upper", &[str_val]))
+            }
+            "lower" => {
+                let str_val = self.compile_str(&args[0]);
+                CodeValue::Str(self.call_runtime("fbl_str_lower", &[str_val]))
+            }
+            _ => unreachable!("Unknown string builtin: {}", name),
+        }
+    }
+
+    // Compile an int of any type, as a word.
+    fn compile_word_int(&mut self, expr: &AstNode) -> Value {
+        let (int_type, value) = self.compile_typed_int(expr);
+        self.convert_int(value, int_type, IntType::I64)
+    }
+
+    // Exponentiation by squaring, with each multiply checked for overflow like `*`.  The base is
+    // only squared again while there are exponent bits left, so it doesn't overflow needlessly.
+    fn compile_pow(
+        &mut self,
+        int_type: IntType,
+        base: Value,
+        exp_type: IntType,
+        exp: Value,
+    ) -> Value {
+        if exp_type.is_signed() {
+            let is_neg = self
+                .fn_builder
+                .ins()
+                .icmp_imm(IntCC::SignedLessThan, exp, 0);
+            self.compile_runtime_check(is_neg, "negative exponent in pow()");
+        }
+        let exp = self.convert_int(exp, exp_type, exp_type.to_unsigned());
+        let exp = self.convert_int(exp, exp_type.to_unsigned(), IntType::U64);
+
+        let int_cl_type = int_cl_type(int_type);
+        let loop_block = self.fn_builder.create_block();
+        let mul_block = self.fn_builder.create_block();
+        let shift_block = self.fn_builder.create_block();
+        let square_block = self.fn_builder.create_block();
+        let done_block = self.fn_builder.create_block();
+        for block in [loop_block, shift_block, square_block] {
+            self.fn_builder.append_block_param(block, int_cl_type);
+            self.fn_builder.append_block_param(block, int_cl_type);
+            self.fn_builder.append_block_param(block, types::I64);
+        }
+        self.fn_builder.append_block_param(done_block, int_cl_type);
+
+        let one = self.fn_builder.ins().iconst(int_cl_type, 1);
+        self.fn_builder.ins().jump(loop_block, &[one, base, exp]);
+
+        // Multiply the result by the base if the low bit of the exponent is set.
+        self.fn_builder.switch_to_block(loop_block);
+        let params = self.fn_builder.block_params(loop_block).to_vec();
+        let (result, base, exp) = (params[0], params[1], params[2]);
+        let low_bit = self.fn_builder.ins().band_imm(exp, 1);
+        self.fn_builder
+            .ins()
+            .brif(low_bit, mul_block, &[], shift_block, &[result, base, exp]);
+
+        self.fn_builder.switch_to_block(mul_block);
+        self.fn_builder.seal_block(mul_block);
+        let product = self.compile_arith("*", int_type, result, base);
+        self.fn_builder
+            .ins()
+            .jump(shift_block, &[product, base, exp]);
+
+        // Then move on to the next bit, stopping when there are none left.
+        self.fn_builder.switch_to_block(shift_block);
+        self.fn_builder.seal_block(shift_block);
+        let params = self.fn_builder.block_params(shift_block).to_vec();
+        let (result, base, exp) = (params[0], params[1], params[2]);
+        let exp = self.fn_builder.ins().ushr_imm(exp, 1);
+        self.fn_builder.ins().brif(
+            exp,
+            square_block,
+            &[result, base, exp],
+            done_block,
+            &[result],
+        );
+
+        self.fn_builder.switch_to_block(square_block);
+        self.fn_builder.seal_block(square_block);
+        let params = self.fn_builder.block_params(square_block).to_vec();
+        let (result, base, exp) = (params[0], params[1], params[2]);
+        let square = self.compile_arith("*", int_type, base, base);
+        self.fn_builder
+            .ins()
+            .jump(loop_block, &[result, square, exp]);
+        self.fn_builder.seal_block(loop_block);
+
+        self.fn_builder.switch_to_block(done_block);
+        self.fn_builder.seal_block(done_block);
+        self.fn_builder.block_params(done_block)[0]
+    }
+
+    // ---------------------------------------------------------------------------------------------
+    // Runtime errors are thrown as exceptions, with a message describing the error.
+
+    fn compile_runtime_check(&mut self, failed: Value, msg: &str) {
+        let error_block = self.fn_builder.create_block();
+        let ok_block = self.fn_builder.create_block();
+        self.fn_builder
+            .ins()
+            .brif(failed, error_block, &[], ok_block, &[]);
+
+        self.fn_builder.switch_to_block(error_block);
+        self.fn_builder.seal_block(error_block);
+        self.compile_throw_str(msg);
+
+        self.fn_builder.switch_to_block(ok_block);
+        self.fn_builder.seal_block(ok_block);
+    }
+
+    fn compile_throw_str(&mut self, msg: &str) {
+        let mut msg = msg.as_bytes().to_vec();
+        msg.push(0);
+        let data_id = self.imm_string(&msg);
+        let msg_ptr = self.data_address(data_id);
+        self.compile_throw(msg_ptr);
+    }
+
+    // Throwing an exception sets the pending flag and the message in the exception state and
+    // jumps to the current handler.  That is either the catch (or finally) block of the innermost
+    // `try`, or the unwind block which returns from the function.  Callers of closures check the
+    // pending flag on return and jump to their own handler if it's set, until it's caught.
+    fn compile_throw(&mut self, msg: Value) {
+        let word_type = self.word_type();
+        let location = self.location();
+        let exc_state = self.data_address(self.exc_state);
+        let pending = self.fn_builder.ins().iconst(word_type, 1);
+        for (value, offset) in [
+            (pending, EXC_PENDING_OFFSET),
+            (msg, EXC_MESSAGE_OFFSET),
+            (location, EXC_LOCATION_OFFSET),
+        ] {
+            self.fn_builder
+                .ins()
+                .store(MemFlags::trusted(), value, exc_state, offset);
+        }
+
+        let handler_block = self.handler_block();
+        self.fn_builder.ins().jump(handler_block, &[]);
+
+        // Anything following the throw is unreachable, but still needs a block to go in.
+        let dead_block = self.fn_builder.create_block();
+        self.fn_builder.switch_to_block(dead_block);
+        self.fn_builder.seal_block(dead_block);
+    }
+
+    fn handler_block(&mut self) -> Block {
+        match self.try_scopes.last() {
+            Some(scope) => scope.handler,
+            None => match self.unwind_block {
+                Some(block) => block,
+                None => {
+                    let block = self.fn_builder.create_block();
+                    self.unwind_block = Some(block);
+                    block
+                }
+            },
+        }
+    }
+
+    // An uncaught exception in a closure just returns, leaving the exception pending for the
+    // caller.  At the top level it is reported and the script halts.
+    fn compile_unwind_block(&mut self) {
+        let unwind_block = match self.unwind_block {
+            Some(block) => block,
+            None => return,
+        };
+        self.fn_builder.switch_to_block(unwind_block);
+
+        let word_type = self.word_type();
+        if self.in_closure {
+            let zero = self.fn_builder.ins().iconst(word_type, 0);
+            self.fn_builder.ins().return_(&[zero]);
+            return;
+        }
+
+        let exc_state = self.data_address(self.exc_state);
+        let location = self.fn_builder.ins().load(
+            word_type,
+            MemFlags::trusted(),
+            exc_state,
+            EXC_LOCATION_OFFSET,
+        );
+        let msg = self.fn_builder.ins().load(
+            word_type,
+            MemFlags::trusted(),
+            exc_state,
+            EXC_MESSAGE_OFFSET,
+        );
+        self.call_runtime_exit("fbl_runtime_error", &[location, msg]);
+    }
+
+    fn compile_try(
+        &mut self,
+        body: &[AstNode],
+        catch: &Option<(String, Vec<AstNode>)>,
+        finally: &Option<Vec<AstNode>>,
+    ) -> CodeValue {
+        let catch_block = self.fn_builder.create_block();
+        let finally_block = self.fn_builder.create_block();
+        let final_block = self.fn_builder.create_block();
+
+        // If there's a `finally` body then exceptions from the catch body, or the `try` body when
+        // there's no `catch`, must go through it on their way to the outer handler.
+        let rethrow_block = finally.as_ref().map(|_| self.fn_builder.create_block());
+
+        // Compile the body, jumping to the catch block for any exceptions.
+        self.try_scopes.push(TryScope {
+            handler: match (catch, rethrow_block) {
+                (None, Some(rethrow_block)) => rethrow_block,
+                _ => catch_block,
+            },
+            finally: finally.clone(),
+        });
+        for stmt in body {
+            self.compile_code(stmt);
+        }
+        self.try_scopes.pop();
+        self.fn_builder.ins().jump(finally_block, &[]);
+
+        // The catch block takes the message from the exception state and clears the pending flag.
+        self.fn_builder.switch_to_block(catch_block);
+        self.fn_builder.seal_block(catch_block);
+        if let Some((name, catch_body)) = catch {
+            let word_type = self.word_type();
+            let exc_state = self.data_address(self.exc_state);
+            let msg = self.fn_builder.ins().load(
+                word_type,
+                MemFlags::trusted(),
+                exc_state,
+                EXC_MESSAGE_OFFSET,
+            );
+            let zero = self.fn_builder.ins().iconst(word_type, 0);
+            self.fn_builder
+                .ins()
+                .store(MemFlags::trusted(), zero, exc_state, EXC_PENDING_OFFSET);
+            self.assign_variable(name, &CodeValue::Str(msg));
+
+            if let Some(rethrow_block) = rethrow_block {
+                self.try_scopes.push(TryScope {
+                    handler: rethrow_block,
+                    finally: finally.clone(),
+                });
+            }
+            for stmt in catch_body {
+                self.compile_code(stmt);
+            }
+            if rethrow_block.is_some() {
+                self.try_scopes.pop();
+            }
+        }
+        self.fn_builder.ins().jump(finally_block, &[]);
+
+        // The finally body is compiled twice, once for falling through and again for when it
+        // needs to rethrow the pending exception.
+        self.fn_builder.switch_to_block(finally_block);
+        self.fn_builder.seal_block(finally_block);
+        for stmt in finally.iter().flatten() {
+            self.compile_code(stmt);
+        }
+        self.fn_builder.ins().jump(final_block, &[]);
+
+        if let Some(rethrow_block) = rethrow_block {
+            self.fn_builder.switch_to_block(rethrow_block);
+            self.fn_builder.seal_block(rethrow_block);
+            for stmt in finally.iter().flatten() {
+                self.compile_code(stmt);
+            }
+            let handler_block = self.handler_block();
+            self.fn_builder.ins().jump(handler_block, &[]);
+        }
+
+        // Switch to final block for rest of program.
+        self.fn_builder.switch_to_block(final_block);
+        self.fn_builder.seal_block(final_block);
+
+        // Need to return a dummy null value.
+        self.null_value()
+    }
+
+    // ---------------------------------------------------------------------------------------------
+    // Fatal errors end the script immediately with a message, which includes the current location.
+
+    fn compile_fatal_error(&mut self, msg: &str) {
+        let location = self.location();
+        let data_id = self.imm_string(format!("{}\0", msg).as_bytes());
+        let msg_ptr = self.data_address(data_id);
+        self.call_runtime_exit("fbl_runtime_error", &[location, msg_ptr]);
+    }
+
+    // Where the statement being compiled is, as a string for runtime error messages, e.g.,
+    // "line 3", or "line 3 of lib/show.fbl" in an imported file.
+    fn location(&mut self) -> Value {
+        let location = match &self.file {
+            Some(file) => format!("line {} of {}\0", self.line, file),
+            None => format!("line {}\0", self.line),
+        };
+        let data_id = self.imm_string(location.as_bytes());
+        self.data_address(data_id)
+    }
+
+    // A failed assertion ends the script with the optional message and the condition source, e.g.,
+    // "Assertion failed on line 3: too big (`x < 10`).".  The message is only evaluated on failure.
+    fn compile_assert(
+        &mut self,
+        cond: &AstNode,
+        cond_text: &str,
+        msg: &Option<Box<AstNode>>,
+    ) -> CodeValue {
+        let cond_value = self.compile_int(cond);
+        let failed_block = self.fn_builder.create_block();
+        let ok_block = self.fn_builder.create_block();
+        self.fn_builder
+            .ins()
+            .brif(cond_value, ok_block, &[], failed_block, &[]);
+
+        self.fn_builder.switch_to_block(failed_block);
+        self.fn_builder.seal_block(failed_block);
+        let msg_value = match msg {
+            Some(msg) => self.compile_str(msg),
+            None => self.word_const(0),
+        };
+        let location = self.location();
+        let data_id = self.imm_string(format!("{}\0", cond_text).as_bytes());
+        let cond_ptr = self.data_address(data_id);
+        self.call_runtime_exit("fbl_assert_failed", &[location, cond_ptr, msg_value]);
+
+        self.fn_builder.switch_to_block(ok_block);
+        self.fn_builder.seal_block(ok_block);
+        self.null_value()
+    }
+
+    fn import_function(&mut self, name: &str, params: &[Type], returns: &[Type]) -> FuncRef {
+        let mut sig = self.module.make_signature();
+        sig.params
+            .extend(params.iter().map(|ty| AbiParam::new(*ty)));
+        sig.returns
+            .extend(returns.iter().map(|ty| AbiParam::new(*ty)));
+        let func_id = self
+            .module
+            .declare_function(name, Linkage::Import, &sig)
+            .unwrap_or_else(|_| panic!("Failed to declare `{}()`", name));
+        self.module
+            .declare_func_in_func(func_id, self.fn_builder.func)
+    }
+
+    fn data_address(&mut self, data_id: DataId) -> Value {
+        let word_type = self.word_type();
+        let local_id = self
+            .module
+            .declare_data_in_func(data_id, self.fn_builder.func);
+        self.fn_builder.ins().symbol_value(word_type, local_id)
+    }
+
+    // Get the data for an immediate string which wasn't in the source, declaring it if needed.
+    fn imm_string(&mut self, str_val: &[u8]) -> DataId {
+        if let Some(data_id) = self.data_map.get(str_val) {
+            return *data_id;
+        }
+
+        let mut data_descr = DataDescription::new();
+        data_descr.define(str_val.to_owned().into_boxed_slice());
+        let data_id = self
+            .module
+            .declare_anonymous_data(false, false)
+            .expect("Declaring a string immediate.");
+        self.module
+            .define_data(data_id, &data_descr)
+            .expect("Defining a string immediate.");
+
+        self.data_map.insert(str_val.to_owned(), data_id);
+        data_id
+    }
+
+    // ---------------------------------------------------------------------------------------------
+
+    fn compile_if(
+        &mut self,
+        cond_expr: &AstNode,
+        true_exprs: &[AstNode],
+        false_exprs: &[AstNode],
+    ) -> CodeValue {
+        let cond_val = self.compile_int(cond_expr);
+
+        let true_block = self.fn_builder.create_block();
+        let false_block = self.fn_builder.create_block();
+        let final_block = self.fn_builder.create_block();
+
+        // Jump to block depending on condition.
+        self.fn_builder
+            .ins()
+            .brif(cond_val, true_block, &[], false_block, &[]);
+
+        // Populate the true block, jump to final block at end.
+        self.fn_builder.switch_to_block(true_block);
+        self.fn_builder.seal_block(true_block);
+        for expr in true_exprs {
+            self.compile_code(expr);
+        }
+        self.fn_builder.ins().jump(final_block, &[]);
+
+        // Populate the false block, jump to the final block at end.
+        self.fn_builder.switch_to_block(false_block);
+        self.fn_builder.seal_block(false_block);
+        for expr in false_exprs {
+            self.compile_code(expr);
+        }
+        self.fn_builder.ins().jump(final_block, &[]);
+
+        // Switch to final block for rest of program.
+        self.fn_builder.switch_to_block(final_block);
+        self.fn_builder.seal_block(final_block);
+
+        // Need to return a dummy null value.
+        self.null_value()
+    }
+
+    // ---------------------------------------------------------------------------------------------
+
+    fn compile_switch(
+        &mut self,
+        expr: &AstNode,
+        cases: &[(Vec<i128>, Vec<AstNode>)],
+        default: &[AstNode],
+    ) -> CodeValue {
+        let (switch_type, switch_val) = self.compile_typed_int(expr);
+
+        // Cranelift's `Switch` will decide whether to use a jump table or a tree of comparisons
+        // depending on how densely packed the case values are.
+        let mut switch = Switch::new();
+        let mut case_blocks = Vec::with_capacity(cases.len());
+        for (values, _) in cases {
+            let case_block = self.fn_builder.create_block();
+            for value in values {
+                if *value < switch_type.min_value() || *value > switch_type.max_value() {
+                    panic!(
+                        "Switch case value {} is out of range for {}.",
+                        value, switch_type
+                    );
+                }
+                // Entries are the bits of the value, so negative values are masked to the width
+                // of the type.
+                let entry = *value as u128 & (u128::MAX >> (128 - switch_type.bits()));
+                if switch.entries().contains_key(&entry) {
+                    panic!("Duplicate switch case value {}.", value);
+                }
+                switch.set_entry(entry, case_block);
+            }
+            case_blocks.push(case_block);
+        }
+        let default_block = self.fn_builder.create_block();
+        let final_block = self.fn_builder.create_block();
+        switch.emit(&mut self.fn_builder, switch_val, default_block);
+
+        // Populate each of the case blocks, which all jump to the final block at the end.
+        let bodies = cases.iter().map(|(_, body)| body.as_slice());
+        for (block, body) in case_blocks.into_iter().zip(bodies) {
+            self.fn_builder.switch_to_block(block);
+            self.fn_builder.seal_block(block);
+            for expr in body {
+                self.compile_code(expr);
+            }
+            self.fn_builder.ins().jump(final_block, &[]);
+        }
+
+        self.fn_builder.switch_to_block(default_block);
+        self.fn_builder.seal_block(default_block);
+        for expr in default {
+            self.compile_code(expr);
+        }
+        self.fn_builder.ins().jump(final_block, &[]);
+
+        // Switch to final block for rest of program.
+        self.fn_builder.switch_to_block(final_block);
+        self.fn_builder.seal_block(final_block);
+
+        // Need to return a dummy null value.
+        self.null_value()
+    }
+
+    // ---------------------------------------------------------------------------------------------
+
+    fn compile_for(
+        &mut self,
+        name: &str,
+        first: &AstNode,
+        last: &AstNode,
+        body: &[AstNode],
+    ) -> CodeValue {
+        // Initialise the iterator.  The bounds are both evaluated once, before the loop.
+        let first_val = self.compile_int_as(first, IntType::I64);
+        let last_val = self.compile_int_as(last, IntType::I64);
+        self.assign_variable(name, &CodeValue::Int(IntType::I64, first_val));
+
+        let cmp_block = self.fn_builder.create_block();
+        let body_block = self.fn_builder.create_block();
+        let final_block = self.fn_builder.create_block();
+
+        self.fn_builder.ins().jump(cmp_block, &[]);
+
+        // The comparison block compares the iterator to last.
+        self.fn_builder.switch_to_block(cmp_block);
+        let iter_var = self.compile_int(&AstNode::Identifier(name.to_string()));
+        let iter_is_lt =
+            self.fn_builder
+                .ins()
+                .icmp(IntCC::SignedLessThanOrEqual, iter_var, last_val);
+        self.fn_builder
+            .ins()
+            .brif(iter_is_lt, body_block, &[], final_block, &[]);
+
+        self.fn_builder.switch_to_block(body_block);
+        for expr in body {
+            self.compile_code(expr);
+        }
+
+        // Stop at last before incrementing, so a loop up to i64::MAX doesn't wrap around forever.
+        let inc_block = self.fn_builder.create_block();
+        let iter_is_last = self.fn_builder.ins().icmp(IntCC::Equal, iter_var, last_val);
+        self.fn_builder
+            .ins()
+            .brif(iter_is_last, final_block, &[], inc_block, &[]);
+        self.fn_builder.switch_to_block(inc_block);
+        let inc_iter_var = self.fn_builder.ins().iadd_imm(iter_var, 1);
+        self.assign_variable(name, &CodeValue::Int(IntType::I64, inc_iter_var));
+        self.fn_builder.ins().jump(cmp_block, &[]);
+
+        // Switch to final block for rest of program.
+        self.fn_builder.switch_to_block(final_block);
+        self.fn_builder.seal_block(cmp_block);
+        self.fn_builder.seal_block(body_block);
+        self.fn_builder.seal_block(inc_block);
+        self.fn_builder.seal_block(final_block);
+
+        // Need to return a dummy null value.
+        self.null_value()
+    }
+
+    // ---------------------------------------------------------------------------------------------
+
+    // Format a line in the runtime's format buffer and print it.  The arguments are all
+    // evaluated first, in case they print too.
+    fn compile_format(&mut self, pieces: &[FormatPiece], args: &[AstNode], output: OutputTarget) {
+        let arg_count = pieces
+            .iter()
+            .filter(|piece| matches!(piece, FormatPiece::Arg(_)))
+            .count();
+        if arg_count != args.len() {
+            panic!(
+                "Format has {} placeholders but {} arguments were given.",
+                arg_count,
+                args.len()
+            );
+        }
+        let mut values = args
+            .iter()
+            .map(|arg| self.compile_code(arg))
+            .collect::<Vec<_>>()
+            .into_iter();
+
+        self.call_runtime("fbl_fmt_begin", &[]);
+        for piece in pieces {
+            match piece {
+                FormatPiece::Text(text) => self.compile_format_text(text),
+                FormatPiece::Arg(spec) => {
+                    let value = values.next().unwrap();
+                    self.compile_format_value(&value, spec);
+                }
+            }
+        }
+        let to_stderr = self.word_const(output.to_stderr as i64);
+        let newline = self.word_const(output.newline as i64);
+        self.call_runtime("fbl_fmt_print", &[to_stderr, newline]);
+    }
+
+    fn compile_format_text(&mut self, text: &str) {
+        let data_id = self.imm_string(format!("{}\0", text).as_bytes());
+        let text_ptr = self.data_address(data_id);
+        let spec = self
+            .fn_builder
+            .ins()
+            .iconst(types::I64, FormatSpec::default().to_word());
+        self.call_runtime("fbl_fmt_str", &[text_ptr, spec]);
+    }
+
+    fn compile_format_value(&mut self, value: &CodeValue, spec: &FormatSpec) {
+        if spec.is_numeric() && !matches!(value, CodeValue::Int(..) | CodeValue::BigInt(_)) {
+            panic!(
+                "Format spec {:?} is only for numbers, found {:?}.",
+                spec, value
+            );
+        }
+        let spec_word = self.fn_builder.ins().iconst(types::I64, spec.to_word());
+        match value {
+            CodeValue::Int(int_type, value) => {
+                // Other radixes show the bits of signed values, so they're printed unsigned.
+                if int_type.is_signed() && spec.radix == Radix::Decimal {
+                    let value = self.convert_int(*value, *int_type, IntType::I64);
+                    self.call_runtime("fbl_fmt_i64", &[value, spec_word]);
+                } else {
+                    let value = self.convert_int(*value, int_type.to_unsigned(), IntType::U64);
+                    self.call_runtime("fbl_fmt_u64", &[value, spec_word]);
+                }
+            }
+            CodeValue::Str(value) => {
+                self.call_runtime("fbl_fmt_str", &[*value, spec_word]);
+            }
+            CodeValue::BigInt(value) => {
+                self.call_runtime("fbl_fmt_bigint", &[*value, spec_word]);
+            }
+            CodeValue::StrArray(value) => {
+                self.call_runtime("fbl_fmt_array", &[*value, spec_word]);
+            }
+            CodeValue::Closure(..) => {
+                let data_id = self.imm_string(b"<closure>\0");
+                let text_ptr = self.data_address(data_id);
+                self.call_runtime("fbl_fmt_str", &[text_ptr, spec_word]);
+            }
+            CodeValue::Tuple(elems) => {
+                // Each element is formatted with the same spec.
+                self.compile_format_text("(");
+                for (idx, elem) in elems.iter().enumerate() {
+                    if idx > 0 {
+                        self.compile_format_text(", ");
+                    }
+                    self.compile_format_value(elem, spec);
+                }
+                self.compile_format_text(")");
+            }
+        }
+    }
+
+    fn compile_print_str(&mut self, str_val: &[u8]) -> Value {
+        let data_id = self.data_map.get(str_val).unwrap();
+        let arg = self.data_address(*data_id);
+        self.compile_print_str_value(arg);
+        arg
+    }
+
+    fn compile_print_str_value(&mut self, arg: Value) {
+        self.call_runtime("fbl_print_str", &[arg]);
+    }
+
+    fn compile_print_int(&mut self, literal: &AstNode) -> Value {
+        let value = self.compile_int_as(literal, IntType::I64);
+        self.compile_print_typed_int(IntType::I64, value);
+        value
+    }
+
+    fn compile_print_value(&mut self, value: &CodeValue) -> Value {
+        match value {
+            CodeValue::Int(int_type, value) => {
+                let value = self.convert_int(*value, *int_type, IntType::I64);
+                self.compile_print_typed_int(*int_type, value);
+                value
+            }
+            CodeValue::Str(value) => {
+                self.compile_print_str_value(*value);
+                *value
+            }
+            CodeValue::BigInt(value) => {
+                let str_val = self.call_runtime("fbl_bigint_to_string", &[*value]);
+                self.compile_print_str_value(str_val);
+                *value
+            }
+            CodeValue::StrArray(_) | CodeValue::Closure(..) | CodeValue::Tuple(_) => {
+                // These are formatted just as they are by `print()` with several values.
+                self.call_runtime("fbl_fmt_begin", &[]);
+                self.compile_format_value(value, &FormatSpec::default());
+                let to_stderr = self.word_const(0);
+                let newline = self.word_const(1);
+                self.call_runtime("fbl_fmt_print", &[to_stderr, newline]);
+                self.word_const(0)
+            }
+        }
+    }
+
+    // Ints are printed in full by the runtime, unless we're printing them the old way.  `value`
+    // has already been extended to 64 bits.
+    fn compile_print_typed_int(&mut self, int_type: IntType, value: Value) {
+        let func_name = if self.padded_ints {
+            "fbl_print_padded"
+        } else if int_type == IntType::U64 {
+            "fbl_print_u64"
+        } else {
+            "fbl_print_i64"
+        };
+        self.call_runtime(func_name, &[value]);
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
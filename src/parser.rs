@@ -1,12 +1,56 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use unicode_xid::UnicodeXID;
+
 // -------------------------------------------------------------------------------------------------
 // AST node.
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum AstNode {
+    // Each statement is wrapped with its line number, for runtime error messages.
+    Stmt {
+        line: usize,
+        stmt: Box<AstNode>,
+    },
     Literal(AstValue),
     Identifier(String),
     Call(String, Vec<AstNode>),
+    Tuple(Vec<AstNode>),
+    Cast(Box<AstNode>, ValueType),
+    Index(Box<AstNode>, Box<AstNode>),
     Assign(String, Box<AstNode>),
+    Declare(String, ValueType, Box<AstNode>),
+    Destructure(Vec<String>, Box<AstNode>),
+    Switch {
+        expr: Box<AstNode>,
+        cases: Vec<(Vec<i128>, Vec<AstNode>)>,
+        default: Vec<AstNode>,
+    },
+    Closure {
+        params: Vec<String>,
+        body: Vec<AstNode>,
+    },
+    Return(Box<AstNode>),
+    Throw(Box<AstNode>),
+    // The source text of the condition is kept for the failure message.
+    Assert {
+        cond: Box<AstNode>,
+        cond_text: String,
+        msg: Option<Box<AstNode>>,
+    },
+    Try {
+        body: Vec<AstNode>,
+        catch: Option<(String, Vec<AstNode>)>,
+        finally: Option<Vec<AstNode>>,
+    },
+    Import(String),
+    // The statements of an imported file, which replace its import.  `path` is used to name the
+    // file in runtime error messages.
+    Imported {
+        path: String,
+        body: Vec<AstNode>,
+    },
     If {
         cond_expr: Box<AstNode>,
         true_expr: Vec<AstNode>,
@@ -14,36 +58,261 @@ pub enum AstNode {
     },
     For {
         ident: String,
-        first: i64,
-        last: i64,
+        first: Box<AstNode>,
+        last: Box<AstNode>,
         body: Vec<AstNode>,
     },
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum AstValue {
-    Int(i64),
+    // Int literals may be up to `u64::MAX`, they're range checked for the type they're used as.
+    Int(i128),
+    // The decimal digits of a bigint literal, which may be any length.
+    BigInt(String),
     Text(Vec<u8>),
 }
 
+// The sized integer types which may be used in annotations and casts.  Unannotated integers are
+// always `i64`.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IntType {
+    I8,
+    I16,
+    I32,
+    I64,
+    U8,
+    U16,
+    U32,
+    U64,
+}
+
+impl IntType {
+    pub fn bits(&self) -> u32 {
+        match self {
+            IntType::I8 | IntType::U8 => 8,
+            IntType::I16 | IntType::U16 => 16,
+            IntType::I32 | IntType::U32 => 32,
+            IntType::I64 | IntType::U64 => 64,
+        }
+    }
+
+    // The unsigned type of the same size.
+    pub fn to_unsigned(self) -> IntType {
+        match self {
+            IntType::I8 | IntType::U8 => IntType::U8,
+            IntType::I16 | IntType::U16 => IntType::U16,
+            IntType::I32 | IntType::U32 => IntType::U32,
+            IntType::I64 | IntType::U64 => IntType::U64,
+        }
+    }
+
+    pub fn is_signed(&self) -> bool {
+        matches!(
+            self,
+            IntType::I8 | IntType::I16 | IntType::I32 | IntType::I64
+        )
+    }
+
+    pub fn min_value(&self) -> i128 {
+        if self.is_signed() {
+            -(1_i128 << (self.bits() - 1))
+        } else {
+            0
+        }
+    }
+
+    pub fn max_value(&self) -> i128 {
+        if self.is_signed() {
+            (1_i128 << (self.bits() - 1)) - 1
+        } else {
+            (1_i128 << self.bits()) - 1
+        }
+    }
+}
+
+impl std::fmt::Display for IntType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let sign = if self.is_signed() { 'i' } else { 'u' };
+        write!(f, "{}{}", sign, self.bits())
+    }
+}
+
+impl std::str::FromStr for IntType {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "i8" => Ok(IntType::I8),
+            "i16" => Ok(IntType::I16),
+            "i32" => Ok(IntType::I32),
+            "i64" => Ok(IntType::I64),
+            "u8" => Ok(IntType::U8),
+            "u16" => Ok(IntType::U16),
+            "u32" => Ok(IntType::U32),
+            "u64" => Ok(IntType::U64),
+            _ => Err(format!("unknown integer type `{}`", name)),
+        }
+    }
+}
+
+// The types which may be used in annotations and casts, which are the sized integers and the
+// arbitrary precision `bigint`.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ValueType {
+    Int(IntType),
+    BigInt,
+}
+
+impl std::fmt::Display for ValueType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ValueType::Int(int_type) => int_type.fmt(f),
+            ValueType::BigInt => write!(f, "bigint"),
+        }
+    }
+}
+
 // -------------------------------------------------------------------------------------------------
 
-pub fn parse_string(input: &str) -> Result<AstNode, std::io::Error> {
-    fbl_parser::parse(input).map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))
+pub fn parse_string(input: &str) -> Result<Vec<AstNode>, std::io::Error> {
+    let line_starts = std::iter::once(0)
+        .chain(input.match_indices('\n').map(|(idx, _)| idx + 1))
+        .collect::<Vec<_>>();
+    fbl_parser::parse(input, &line_starts, input).map_err(std::io::Error::other)
+}
+
+// Parse a program, replacing each of its imports with an `Imported` node holding the statements from
+// the imported file.  Import paths are relative to the directory of the importing file, or
+// `base_dir` for the top level.  Each file is only included once, the first time it's imported.
+
+pub fn parse_program(input: &str, base_dir: &Path) -> Result<Vec<AstNode>, std::io::Error> {
+    let mut importer = Importer {
+        loaded: HashSet::new(),
+        stack: Vec::new(),
+    };
+    importer.expand(parse_string(input)?, base_dir)
+}
+
+pub fn parse_file(path: &Path) -> Result<Vec<AstNode>, std::io::Error> {
+    let mut importer = Importer {
+        loaded: HashSet::new(),
+        stack: Vec::new(),
+    };
+    importer.import(path)
+}
+
+struct Importer {
+    // Every file which has been imported so far.
+    loaded: HashSet<PathBuf>,
+    // The chain of files currently being imported, used to detect cycles.
+    stack: Vec<PathBuf>,
+}
+
+impl Importer {
+    fn import(&mut self, path: &Path) -> Result<Vec<AstNode>, std::io::Error> {
+        let path = path.canonicalize().map_err(|err| {
+            std::io::Error::new(err.kind(), format!("{}: {}", path.display(), err))
+        })?;
+
+        if self.stack.contains(&path) {
+            let cycle = self
+                .stack
+                .iter()
+                .skip_while(|p| **p != path)
+                .chain(std::iter::once(&path))
+                .map(|p| p.display().to_string())
+                .collect::<Vec<_>>();
+            return Err(std::io::Error::other(format!(
+                "Import cycle: {}",
+                cycle.join(" -> ")
+            )));
+        }
+        if !self.loaded.insert(path.clone()) {
+            return Ok(Vec::new());
+        }
+
+        let input = std::fs::read_to_string(&path)?;
+        let program = parse_string(&input)
+            .map_err(|err| std::io::Error::other(format!("{}: {}", path.display(), err)))?;
+
+        self.stack.push(path.clone());
+        let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
+        let program = self.expand(program, base_dir);
+        self.stack.pop();
+        program
+    }
+
+    fn expand(
+        &mut self,
+        program: Vec<AstNode>,
+        base_dir: &Path,
+    ) -> Result<Vec<AstNode>, std::io::Error> {
+        let mut expanded = Vec::new();
+        for stmt in program {
+            match stmt {
+                AstNode::Import(path) => {
+                    let path = base_dir.join(path);
+                    let body = self.import(&path)?;
+                    if !body.is_empty() {
+                        expanded.push(AstNode::Imported {
+                            path: display_path(&path),
+                            body,
+                        });
+                    }
+                }
+                stmt => expanded.push(stmt),
+            }
+        }
+        Ok(expanded)
+    }
+}
+
+// Imported files are named relative to the current directory when they're within it.
+fn display_path(path: &Path) -> String {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let relative = std::env::current_dir()
+        .ok()
+        .and_then(|dir| path.strip_prefix(dir).ok().map(Path::to_path_buf));
+    relative.unwrap_or(path).display().to_string()
 }
 
 // -------------------------------------------------------------------------------------------------
 
 peg::parser! {
-    grammar fbl_parser() for str {
-        pub rule parse() -> AstNode
-            = _ s:stmt() eoi() {
-                s
+    // The source is passed in again so that statements may quote it.
+    grammar fbl_parser(line_starts: &[usize], source: &str) for str {
+        // Imports may only appear at the top level.
+        pub rule parse() -> Vec<AstNode>
+            = _ ss:(import_stmt() / stmt())* eoi() {
+                ss
             }
 
+        rule import_stmt() -> AstNode
+            = "import" _ "\"" p:$((!"\"" [_])*) "\"" _ ";" _ {
+                AstNode::Import(p.to_string())
+            }
+            / expected!("import")
+
         rule stmt() -> AstNode
+            = l:line() s:stmt_kind() {
+                AstNode::Stmt {
+                    line: l,
+                    stmt: Box::new(s),
+                }
+            }
+
+        rule stmt_kind() -> AstNode
             = for_loop_stmt()
             / if_stmt()
+            / switch_stmt()
+            / return_stmt()
+            / throw_stmt()
+            / assert_stmt()
+            / try_stmt()
+            / destructure_stmt()
             / assign_stmt()
             / e:expr() ";" _ { e }
 
@@ -52,15 +321,15 @@ peg::parser! {
                 ss
             }
 
-        // For-loops must have immediate first and last range values.
+        // For-loops have first and last range values, which are evaluated once before the loop.
         rule for_loop_stmt() -> AstNode
-            = "for" _ "(" _ id:ident() ";" _ fst:num() "," _ lst:num() ")" _ "{" _
+            = "for" _ "(" _ id:ident() ";" _ fst:expr() "," _ lst:expr() ")" _ "{" _
                   b:stmt_list()
               "}" _ {
                 AstNode::For {
                     ident: id,
-                    first: fst,
-                    last: lst,
+                    first: Box::new(fst),
+                    last: Box::new(lst),
                     body: b,
                 }
             }
@@ -80,30 +349,145 @@ peg::parser! {
             }
             / expected!("if statement")
 
+        // Switch cases must be immediates and there is no fall-through between them.
+        rule switch_stmt() -> AstNode
+            = "switch" _ "(" _ e:expr() ")" _ "{" _
+                cs:switch_case()*
+                d:("default" _ ":" _ ds:stmt_list() { ds })?
+            "}" _ {
+                AstNode::Switch {
+                    expr: Box::new(e),
+                    cases: cs,
+                    default: d.unwrap_or_default(),
+                }
+            }
+            / expected!("switch statement")
+
+        rule switch_case() -> (Vec<i128>, Vec<AstNode>)
+            = "case" _ vs:(case_value() ++ ("," _)) ":" _ ss:stmt_list() {
+                (vs, ss)
+            }
+
+        rule case_value() -> i128
+            = "-" n:num() { -n }
+            / num()
+
         rule assign_stmt() -> AstNode
-            = i:ident() "=" _ e:expr() ";" _ {
+            = i:ident() ":" _ t:value_type() "=" _ e:expr() ";" _ {
+                AstNode::Declare(i, t, Box::new(e))
+            }
+            / i:ident() "=" _ e:expr() ";" _ {
                 AstNode::Assign(i, Box::new(e))
             }
             / expected!("assignment")
 
+        rule return_stmt() -> AstNode
+            = "return" _ e:expr() ";" _ {
+                AstNode::Return(Box::new(e))
+            }
+            / expected!("return statement")
+
+        rule throw_stmt() -> AstNode
+            = "throw" _ e:expr() ";" _ {
+                AstNode::Throw(Box::new(e))
+            }
+            / expected!("throw statement")
+
+        rule assert_stmt() -> AstNode
+            = "assert" _ "(" _ start:position!() c:expr() end:position!()
+                m:("," _ m:expr() { m })? ")" _ ";" _ {
+                AstNode::Assert {
+                    cond: Box::new(c),
+                    cond_text: source[start..end].trim_end().to_string(),
+                    msg: m.map(Box::new),
+                }
+            }
+            / expected!("assert statement")
+
+        // A `try` must have a `catch`, a `finally` or both.
+        rule try_stmt() -> AstNode
+            = "try" _ "{" _ ts:stmt_list() "}" _
+                c:("catch" _ "(" _ i:ident() ")" _ "{" _ cs:stmt_list() "}" _ { (i, cs) })?
+                f:("finally" _ "{" _ fs:stmt_list() "}" _ { fs })? {?
+                if c.is_none() && f.is_none() {
+                    Err("catch or finally")
+                } else {
+                    Ok(AstNode::Try {
+                        body: ts,
+                        catch: c,
+                        finally: f,
+                    })
+                }
+            }
+            / expected!("try statement")
+
+        rule destructure_stmt() -> AstNode
+            = "(" _ is:(ident() **<2,> ("," _)) ")" _ "=" _ e:expr() ";" _ {
+                AstNode::Destructure(is, Box::new(e))
+            }
+            / expected!("destructuring assignment")
+
         rule expr() -> AstNode
             = precedence! {
                 l:(@) "&&" _ r:@ { AstNode::Call("&&".to_string(), vec![l, r]) }
                 --
                 l:(@) "==" _ r:@ { AstNode::Call("==".to_string(), vec![l, r]) }
+                l:(@) "!=" _ r:@ { AstNode::Call("!=".to_string(), vec![l, r]) }
+                --
+                l:(@) "<=" _ r:@ { AstNode::Call("<=".to_string(), vec![l, r]) }
+                l:(@) "<" !"<" _ r:@ { AstNode::Call("<".to_string(), vec![l, r]) }
+                l:(@) ">=" _ r:@ { AstNode::Call(">=".to_string(), vec![l, r]) }
+                l:(@) ">" !">" _ r:@ { AstNode::Call(">".to_string(), vec![l, r]) }
+                --
+                l:(@) "|" _ r:@ { AstNode::Call("|".to_string(), vec![l, r]) }
                 --
+                l:(@) "^" _ r:@ { AstNode::Call("^".to_string(), vec![l, r]) }
+                --
+                l:(@) "&" !"&" _ r:@ { AstNode::Call("&".to_string(), vec![l, r]) }
+                --
+                l:(@) "<<" _ r:@ { AstNode::Call("<<".to_string(), vec![l, r]) }
+                l:(@) ">>>" _ r:@ { AstNode::Call(">>>".to_string(), vec![l, r]) }
+                l:(@) ">>" _ r:@ { AstNode::Call(">>".to_string(), vec![l, r]) }
+                --
+                l:(@) "+" _ r:@ { AstNode::Call("+".to_string(), vec![l, r]) }
+                l:(@) "-" _ r:@ { AstNode::Call("-".to_string(), vec![l, r]) }
+                --
+                l:(@) "*" _ r:@ { AstNode::Call("*".to_string(), vec![l, r]) }
+                l:(@) "/" _ r:@ { AstNode::Call("/".to_string(), vec![l, r]) }
                 l:(@) "%" _ r:@ { AstNode::Call("%".to_string(), vec![l, r]) }
                 --
+                e:(@) "as" !id_char() _ t:value_type() { AstNode::Cast(Box::new(e), t) }
+                --
+                e:(@) "[" _ i:expr() "]" _ { AstNode::Index(Box::new(e), Box::new(i)) }
+                --
+                "~" _ e:@ { AstNode::Call("~".to_string(), vec![e]) }
+                --
                 t:term() { t }
             }
             / expected!("expression")
 
         rule term() -> AstNode
-            = call_expr()
+            = closure_expr()
+            / call_expr()
             / i:ident() { AstNode::Identifier(i) }
             / l:literal() { AstNode::Literal(l) }
+            / "(" _ es:(expr() **<2,> ("," _)) ")" _ { AstNode::Tuple(es) }
             / "(" _ e:expr() ")" _ { e }
 
+        // Closures have either a single expression, which is returned, or a block body.
+        rule closure_expr() -> AstNode
+            = "|" _ ps:(ident() ** ("," _)) "|" _ b:closure_body() {
+                AstNode::Closure {
+                    params: ps,
+                    body: b,
+                }
+            }
+            / expected!("closure")
+
+        rule closure_body() -> Vec<AstNode>
+            = "{" _ ss:stmt_list() "}" _ { ss }
+            / e:expr() { vec![AstNode::Return(Box::new(e))] }
+
         rule call_expr() -> AstNode
             = i:ident() "(" _ args:(expr() ** ("," _)) ")" _ {
                 AstNode::Call(i, args)
@@ -115,17 +499,21 @@ peg::parser! {
                 id.to_string()
             }
 
+        // Identifiers are Unicode XID, plus a leading underscore is allowed.
         rule id_char0()
-            = ['A'..='Z' | 'a'..='z' | '_']
+            = [c if c == '_' || UnicodeXID::is_xid_start(c)]
 
         rule id_char()
-            = id_char0() / ['0'..='9']
+            = [c if UnicodeXID::is_xid_continue(c)]
 
         rule keyword()
-            = "for" / "if" / "else"
+            = ("for" / "if" / "else" / "switch" / "case" / "default" / "return" / "import" / "as" / "throw" / "try" / "assert" / "catch" / "finally") !id_char()
 
         rule literal() -> AstValue
-            = n:num() {
+            = i:$(['0'..='9']+) "n" !id_char() _ {
+                AstValue::BigInt(i.to_string())
+            }
+            / n:num() {
                 AstValue::Int(n)
             }
             / "\"" s:$((!"\"" [_])*) "\"" _ {
@@ -135,9 +523,25 @@ peg::parser! {
                 AstValue::Text(v)
             }
 
-        rule num() -> i64
-            = i:$(['0'..='9']+) _ {
-                i.parse::<i64>().unwrap()
+        rule num() -> i128
+            = i:$(['0'..='9']+) _ {?
+                i.parse::<u64>().map(i128::from).or(Err("integer literal within u64 range"))
+            }
+
+        rule value_type() -> ValueType
+            = t:int_type() { ValueType::Int(t) }
+            / "bigint" !id_char() _ { ValueType::BigInt }
+            / expected!("type")
+
+        rule int_type() -> IntType
+            = t:$(['i' | 'u'] ("8" / "16" / "32" / "64")) !id_char() _ {
+                t.parse().unwrap()
+            }
+            / expected!("integer type")
+
+        rule line() -> usize
+            = p:position!() {
+                line_starts.partition_point(|start| *start <= p)
             }
 
         rule _()
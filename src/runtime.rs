@@ -0,0 +1,815 @@
+use std::cell::{Cell, RefCell};
+use std::convert::TryFrom;
+use std::ffi::{c_char, CStr, CString};
+use std::fs::File;
+use std::io::{BufRead, BufWriter, Read, Seek, SeekFrom, StdinLock, Stdout, Write};
+
+use num_bigint::BigInt;
+
+use crate::format::{FormatSpec, Radix};
+use crate::RunError;
+
+// -------------------------------------------------------------------------------------------------
+// The runtime library holds the builtins which are called by the compiled code, rather than being
+// generated inline.  They're registered with the JIT by name in `symbols()`.  All of them take and
+// return words, even those with nothing useful to return.
+
+pub fn symbols() -> Vec<(&'static str, *const u8)> {
+    vec![
+        ("fbl_alloc", fbl_alloc as *const u8),
+        ("fbl_exit", fbl_exit as *const u8),
+        ("fbl_flush", fbl_flush as *const u8),
+        ("fbl_runtime_error", fbl_runtime_error as *const u8),
+        ("fbl_take_error", fbl_take_error as *const u8),
+        ("fbl_assert_failed", fbl_assert_failed as *const u8),
+        ("fbl_print_str", fbl_print_str as *const u8),
+        ("fbl_print_padded", fbl_print_padded as *const u8),
+        ("fbl_bigint_parse", fbl_bigint_parse as *const u8),
+        ("fbl_bigint_from_i64", fbl_bigint_from_i64 as *const u8),
+        ("fbl_bigint_from_u64", fbl_bigint_from_u64 as *const u8),
+        ("fbl_bigint_to_i64", fbl_bigint_to_i64 as *const u8),
+        ("fbl_bigint_to_string", fbl_bigint_to_string as *const u8),
+        ("fbl_bigint_add", fbl_bigint_add as *const u8),
+        ("fbl_bigint_sub", fbl_bigint_sub as *const u8),
+        ("fbl_bigint_mul", fbl_bigint_mul as *const u8),
+        ("fbl_bigint_div", fbl_bigint_div as *const u8),
+        ("fbl_bigint_rem", fbl_bigint_rem as *const u8),
+        ("fbl_bigint_cmp", fbl_bigint_cmp as *const u8),
+        ("fbl_bigint_is_zero", fbl_bigint_is_zero as *const u8),
+        ("fbl_print_i64", fbl_print_i64 as *const u8),
+        ("fbl_print_u64", fbl_print_u64 as *const u8),
+        ("fbl_read_line", fbl_read_line as *const u8),
+        ("fbl_read_int", fbl_read_int as *const u8),
+        ("fbl_fmt_begin", fbl_fmt_begin as *const u8),
+        ("fbl_fmt_str", fbl_fmt_str as *const u8),
+        ("fbl_fmt_i64", fbl_fmt_i64 as *const u8),
+        ("fbl_fmt_u64", fbl_fmt_u64 as *const u8),
+        ("fbl_fmt_bigint", fbl_fmt_bigint as *const u8),
+        ("fbl_fmt_print", fbl_fmt_print as *const u8),
+        ("fbl_fmt_array", fbl_fmt_array as *const u8),
+        ("fbl_str_len", fbl_str_len as *const u8),
+        ("fbl_str_char_at", fbl_str_char_at as *const u8),
+        ("fbl_str_substr", fbl_str_substr as *const u8),
+        ("fbl_str_find", fbl_str_find as *const u8),
+        ("fbl_str_replace", fbl_str_replace as *const u8),
+        ("fbl_str_split", fbl_str_split as *const u8),
+        ("fbl_str_trim", fbl_str_trim as *const u8),
+        ("fbl_str_upper", fbl_str_upper as *const u8),
+        ("fbl_str_lower", fbl_str_lower as *const u8),
+        ("fbl_str_starts_with", fbl_str_starts_with as *const u8),
+        ("fbl_str_repeat", fbl_str_repeat as *const u8),
+        ("fbl_i64_to_string", fbl_i64_to_string as *const u8),
+        ("fbl_u64_to_string", fbl_u64_to_string as *const u8),
+        ("fbl_parse_int", fbl_parse_int as *const u8),
+        ("fbl_gcd", fbl_gcd as *const u8),
+        ("fbl_isqrt", fbl_isqrt as *const u8),
+        ("fbl_getenv", fbl_getenv as *const u8),
+        ("fbl_has_env", fbl_has_env as *const u8),
+        ("fbl_args", fbl_args as *const u8),
+        ("fbl_argc", fbl_argc as *const u8),
+        ("fbl_array_len", fbl_array_len as *const u8),
+        ("fbl_array_get", fbl_array_get as *const u8),
+        ("fbl_file_open", fbl_file_open as *const u8),
+        ("fbl_file_close", fbl_file_close as *const u8),
+        ("fbl_file_read_all", fbl_file_read_all as *const u8),
+        ("fbl_file_read_lines", fbl_file_read_lines as *const u8),
+        ("fbl_file_write", fbl_file_write as *const u8),
+        ("fbl_path_read_all", fbl_path_read_all as *const u8),
+        ("fbl_path_read_lines", fbl_path_read_lines as *const u8),
+        ("fbl_path_write", fbl_path_write as *const u8),
+    ]
+}
+
+// Each run starts afresh with its own arguments, without any state left over from a previous run,
+// such as open files or a pending error.
+
+pub fn reset(args: &[String]) {
+    flush();
+    EXIT.with(|exit| *exit.borrow_mut() = None);
+    ERROR.with(|error| *error.borrow_mut() = None);
+    FORMAT_BUF.with(|buf| buf.borrow_mut().clear());
+    FILES.with(|files| files.borrow_mut().clear());
+    SCRIPT_ARGS.with(|script_args| script_args.set(new_array(args)));
+}
+
+// -------------------------------------------------------------------------------------------------
+// Allocations are zeroed and word aligned.  There is no garbage collection, so they're never
+// freed.
+
+extern "C" fn fbl_alloc(size: usize) -> *mut u64 {
+    let words = size.div_ceil(8);
+    Box::leak(vec![0_u64; words].into_boxed_slice()).as_mut_ptr()
+}
+
+// -------------------------------------------------------------------------------------------------
+// Exiting, either by request or because of an error.  These don't exit the process, they record
+// how the script finished and the compiled code then returns all the way out of its main function,
+// for `run()` to take the result with `take_exit()`.
+
+thread_local! {
+    static EXIT: RefCell<Option<Result<i64, RunError>>> = const { RefCell::new(None) };
+}
+
+fn set_exit(exit: Result<i64, RunError>) -> i64 {
+    flush();
+    EXIT.with(|cell| *cell.borrow_mut() = Some(exit));
+    0
+}
+
+pub fn take_exit() -> Option<Result<i64, RunError>> {
+    EXIT.with(|cell| cell.borrow_mut().take())
+}
+
+extern "C" fn fbl_exit(status: i64) -> i64 {
+    set_exit(Ok(status))
+}
+
+// The location is where the error happened, e.g., "line 3" or "line 3 of lib/show.fbl".
+extern "C" fn fbl_runtime_error(location: *const c_char, msg: *const c_char) -> i64 {
+    set_exit(Err(RunError::Runtime(format!(
+        "Runtime error on {}: {}.",
+        str_ref(location),
+        str_ref(msg)
+    ))))
+}
+
+// Runtime functions which can fail set an error message, which the compiled code takes straight
+// after the call and throws if it isn't null.
+
+thread_local! {
+    static ERROR: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+fn set_error(msg: String) {
+    ERROR.with(|error| *error.borrow_mut() = Some(msg));
+}
+
+extern "C" fn fbl_take_error() -> *const c_char {
+    ERROR.with(|error| match error.borrow_mut().take() {
+        Some(msg) => new_str(&msg),
+        None => std::ptr::null(),
+    })
+}
+
+// The message is optional and may be null.
+extern "C" fn fbl_assert_failed(
+    location: *const c_char,
+    cond: *const c_char,
+    msg: *const c_char,
+) -> i64 {
+    let failure = if msg.is_null() {
+        format!(
+            "Assertion failed on {}: `{}`.",
+            str_ref(location),
+            str_ref(cond)
+        )
+    } else {
+        format!(
+            "Assertion failed on {}: {} (`{}`).",
+            str_ref(location),
+            str_ref(msg),
+            str_ref(cond)
+        )
+    };
+    set_exit(Err(RunError::Assert(failure)))
+}
+
+// -------------------------------------------------------------------------------------------------
+// Printing, where each value is followed by a newline.  All output is buffered and only written
+// when the buffer is full, the script calls `flush()`, exits or fails, or `flush()` below is called
+// once the script has finished.  Errors writing to stdout, e.g., a closed pipe, are ignored.
+
+thread_local! {
+    static OUTPUT: RefCell<BufWriter<Stdout>> =
+        RefCell::new(BufWriter::with_capacity(64 * 1024, std::io::stdout()));
+}
+
+pub fn flush() {
+    OUTPUT.with(|output| {
+        let _ = output.borrow_mut().flush();
+    });
+}
+
+extern "C" fn fbl_flush() -> i64 {
+    flush();
+    0
+}
+
+fn print_line(line: impl std::fmt::Display) -> i64 {
+    OUTPUT.with(|output| {
+        let _ = writeln!(output.borrow_mut(), "{}", line);
+    });
+    0
+}
+
+extern "C" fn fbl_print_str(value: *const c_char) -> i64 {
+    print_line(str_ref(value))
+}
+
+// The original int printing, which shows the last three digits padded with spaces.
+extern "C" fn fbl_print_padded(value: u64) -> i64 {
+    print_line(format_args!("{:>3}", value % 1000))
+}
+
+extern "C" fn fbl_print_i64(value: i64) -> i64 {
+    print_line(value)
+}
+
+extern "C" fn fbl_print_u64(value: u64) -> i64 {
+    print_line(value)
+}
+
+// -------------------------------------------------------------------------------------------------
+// Reading from stdin, a line at a time.  Stdout is flushed first so any prompt is shown.
+
+thread_local! {
+    static INPUT: RefCell<StdinLock<'static>> = RefCell::new(std::io::stdin().lock());
+}
+
+// Returns the line without its line ending, or None at the end of the input.
+fn read_line() -> Option<String> {
+    flush();
+    let mut line = Vec::new();
+    match INPUT.with(|input| input.borrow_mut().read_until(b'\n', &mut line)) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => {
+            if line.ends_with(b"\n") {
+                line.pop();
+                if line.ends_with(b"\r") {
+                    line.pop();
+                }
+            }
+            line.retain(|b| *b != 0);
+            Some(String::from_utf8_lossy(&line).into_owned())
+        }
+    }
+}
+
+// Returns null at the end of the input.
+extern "C" fn fbl_read_line() -> *const c_char {
+    read_line().map_or(std::ptr::null(), |line| new_str(&line))
+}
+
+extern "C" fn fbl_read_int() -> i64 {
+    match read_line() {
+        None => {
+            set_error("end of input in read_int()".to_string());
+            0
+        }
+        Some(line) => parse_int(&line, "malformed integer input"),
+    }
+}
+
+// Leading and trailing whitespace is ignored.
+fn parse_int(value: &str, error_msg: &str) -> i64 {
+    value.trim().parse().unwrap_or_else(|_| {
+        set_error(format!("{} '{}'", error_msg, value));
+        0
+    })
+}
+
+// -------------------------------------------------------------------------------------------------
+// Formatted printing builds a line in the format buffer, one value at a time, and then prints it.
+// Each value has its own spec, packed into a word by `FormatSpec::to_word()`.
+
+thread_local! {
+    static FORMAT_BUF: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+fn format_push(value: &str) -> i64 {
+    FORMAT_BUF.with(|buf| buf.borrow_mut().push_str(value));
+    0
+}
+
+fn radix_digits(value: u64, radix: Radix) -> String {
+    match radix {
+        Radix::Decimal => value.to_string(),
+        Radix::LowerHex | Radix::UpperHex => format!("{:x}", value),
+        Radix::Octal => format!("{:o}", value),
+        Radix::Binary => format!("{:b}", value),
+    }
+}
+
+extern "C" fn fbl_fmt_begin() -> i64 {
+    FORMAT_BUF.with(|buf| buf.borrow_mut().clear());
+    0
+}
+
+extern "C" fn fbl_fmt_str(value: *const c_char, spec: i64) -> i64 {
+    format_push(&FormatSpec::from_word(spec).format_str(str_ref(value)))
+}
+
+extern "C" fn fbl_fmt_i64(value: i64, spec: i64) -> i64 {
+    let spec = FormatSpec::from_word(spec);
+    let digits = radix_digits(value.unsigned_abs(), spec.radix);
+    format_push(&spec.format_number(value < 0, &digits))
+}
+
+extern "C" fn fbl_fmt_u64(value: u64, spec: i64) -> i64 {
+    let spec = FormatSpec::from_word(spec);
+    let digits = radix_digits(value, spec.radix);
+    format_push(&spec.format_number(false, &digits))
+}
+
+extern "C" fn fbl_fmt_bigint(value: *const BigInt, spec: i64) -> i64 {
+    let spec = FormatSpec::from_word(spec);
+    let value = bigint_ref(value);
+    let digits = value.magnitude().to_str_radix(spec.radix_base());
+    format_push(&spec.format_number(value.sign() == num_bigint::Sign::Minus, &digits))
+}
+
+extern "C" fn fbl_fmt_array(value: *const StrArray, spec: i64) -> i64 {
+    let spec = FormatSpec::from_word(spec);
+    let elems = array_ref(value)
+        .iter()
+        .map(|elem| spec.format_str(str_ref(*elem)))
+        .collect::<Vec<_>>();
+    format_push(&format!("[{}]", elems.join(", ")))
+}
+
+// Stdout is flushed before writing to stderr, which isn't buffered, to keep them in order.
+extern "C" fn fbl_fmt_print(to_stderr: i64, newline: i64) -> i64 {
+    FORMAT_BUF.with(|buf| {
+        let buf = buf.borrow();
+        let end = if newline != 0 { "\n" } else { "" };
+        if to_stderr != 0 {
+            flush();
+            eprint!("{}{}", buf, end);
+        } else {
+            OUTPUT.with(|output| {
+                let _ = write!(output.borrow_mut(), "{}{}", buf, end);
+            });
+        }
+    });
+    0
+}
+
+// -------------------------------------------------------------------------------------------------
+// Bigints are immutable and passed around as pointers.  Every operation returns a new one.  There
+// is no garbage collection so, like closure environments, they're never freed.
+
+fn new_bigint(value: BigInt) -> *mut BigInt {
+    Box::into_raw(Box::new(value))
+}
+
+// Safety: all the bigint pointers passed from compiled code were returned by `new_bigint()` and
+// are never freed.
+fn bigint_ref<'a>(ptr: *const BigInt) -> &'a BigInt {
+    unsafe { &*ptr }
+}
+
+// The compiler has already checked the literal is only decimal digits.
+extern "C" fn fbl_bigint_parse(digits: *const c_char) -> *mut BigInt {
+    let digits = unsafe { CStr::from_ptr(digits) };
+    new_bigint(
+        BigInt::parse_bytes(digits.to_bytes(), 10).expect("Malformed bigint literal digits."),
+    )
+}
+
+extern "C" fn fbl_bigint_from_i64(value: i64) -> *mut BigInt {
+    new_bigint(BigInt::from(value))
+}
+
+extern "C" fn fbl_bigint_from_u64(value: u64) -> *mut BigInt {
+    new_bigint(BigInt::from(value))
+}
+
+// Converting to an int keeps only the low 64 bits, like casting to a narrower int type.
+extern "C" fn fbl_bigint_to_i64(value: *const BigInt) -> i64 {
+    let value = bigint_ref(value);
+    let low = value.iter_u64_digits().next().unwrap_or(0) as i64;
+    if value.sign() == num_bigint::Sign::Minus {
+        low.wrapping_neg()
+    } else {
+        low
+    }
+}
+
+// The returned string is leaked, like the bigints themselves.
+extern "C" fn fbl_bigint_to_string(value: *const BigInt) -> *const c_char {
+    new_str(&bigint_ref(value).to_string())
+}
+
+extern "C" fn fbl_bigint_add(lhs: *const BigInt, rhs: *const BigInt) -> *mut BigInt {
+    new_bigint(bigint_ref(lhs) + bigint_ref(rhs))
+}
+
+extern "C" fn fbl_bigint_sub(lhs: *const BigInt, rhs: *const BigInt) -> *mut BigInt {
+    new_bigint(bigint_ref(lhs) - bigint_ref(rhs))
+}
+
+extern "C" fn fbl_bigint_mul(lhs: *const BigInt, rhs: *const BigInt) -> *mut BigInt {
+    new_bigint(bigint_ref(lhs) * bigint_ref(rhs))
+}
+
+// Division and remainder truncate towards zero, the same as for ints.  The compiled code checks
+// for a zero divisor first.
+extern "C" fn fbl_bigint_div(lhs: *const BigInt, rhs: *const BigInt) -> *mut BigInt {
+    new_bigint(bigint_ref(lhs) / bigint_ref(rhs))
+}
+
+extern "C" fn fbl_bigint_rem(lhs: *const BigInt, rhs: *const BigInt) -> *mut BigInt {
+    new_bigint(bigint_ref(lhs) % bigint_ref(rhs))
+}
+
+// Returns -1, 0 or 1.
+extern "C" fn fbl_bigint_cmp(lhs: *const BigInt, rhs: *const BigInt) -> i64 {
+    bigint_ref(lhs).cmp(bigint_ref(rhs)) as i64
+}
+
+extern "C" fn fbl_bigint_is_zero(value: *const BigInt) -> i64 {
+    (bigint_ref(value).sign() == num_bigint::Sign::NoSign) as i64
+}
+
+// -------------------------------------------------------------------------------------------------
+// Math builtins which aren't compiled inline.  Ints of all types are passed extended to a word.
+
+// The gcd of the magnitudes, as a u64 since `gcd(i64::MIN, 0)` is 2^63.
+extern "C" fn fbl_gcd(lhs: i64, rhs: i64, signed: i64) -> u64 {
+    let (mut a, mut b) = if signed == 0 {
+        (lhs as u64, rhs as u64)
+    } else {
+        (lhs.unsigned_abs(), rhs.unsigned_abs())
+    };
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+extern "C" fn fbl_isqrt(value: i64, signed: i64) -> i64 {
+    if signed == 0 {
+        (value as u64).isqrt() as i64
+    } else if value < 0 {
+        set_error(format!("isqrt() of negative number {}", value));
+        0
+    } else {
+        value.isqrt()
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+// Strings are null terminated UTF-8.  Like bigints, new strings are leaked.
+
+// Safety: all the string pointers passed from compiled code are null terminated and are either
+// literals or were returned by the runtime.  A null string, e.g., from reading past the end of the
+// input, is treated as empty.
+fn str_ref<'a>(ptr: *const c_char) -> &'a str {
+    if ptr.is_null() {
+        return "";
+    }
+    unsafe { CStr::from_ptr(ptr) }
+        .to_str()
+        .expect("Strings are always UTF-8.")
+}
+
+fn new_str(value: &str) -> *const c_char {
+    CString::new(value)
+        .expect("Strings have no nulls.")
+        .into_raw()
+}
+
+extern "C" fn fbl_str_len(value: *const c_char) -> i64 {
+    str_ref(value).chars().count() as i64
+}
+
+// Returns null if `idx` is out of range.
+extern "C" fn fbl_str_char_at(value: *const c_char, idx: i64) -> *const c_char {
+    usize::try_from(idx)
+        .ok()
+        .and_then(|idx| str_ref(value).chars().nth(idx))
+        .map_or(std::ptr::null(), |c| new_str(c.encode_utf8(&mut [0; 4])))
+}
+
+extern "C" fn fbl_str_substr(value: *const c_char, start: i64, count: i64) -> *const c_char {
+    let value = str_ref(value);
+    let len = value.chars().count() as i64;
+    if start < 0 || count < 0 || start > len - count {
+        set_error(format!(
+            "substring of {} characters from {} out of range for length {}",
+            count, start, len
+        ));
+        return std::ptr::null();
+    }
+    let substr = value
+        .chars()
+        .skip(start as usize)
+        .take(count as usize)
+        .collect::<String>();
+    new_str(&substr)
+}
+
+// Returns the character index, or -1 if `sub` isn't found.
+extern "C" fn fbl_str_find(value: *const c_char, sub: *const c_char) -> i64 {
+    let value = str_ref(value);
+    value
+        .find(str_ref(sub))
+        .map_or(-1, |byte_idx| value[..byte_idx].chars().count() as i64)
+}
+
+extern "C" fn fbl_str_replace(
+    value: *const c_char,
+    from: *const c_char,
+    to: *const c_char,
+) -> *const c_char {
+    new_str(&str_ref(value).replace(str_ref(from), str_ref(to)))
+}
+
+extern "C" fn fbl_str_split(value: *const c_char, sep: *const c_char) -> *const StrArray {
+    let sep = str_ref(sep);
+    if sep.is_empty() {
+        set_error("empty separator in split()".to_string());
+        return std::ptr::null();
+    }
+    let parts = str_ref(value)
+        .split(sep)
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    new_array(&parts)
+}
+
+extern "C" fn fbl_str_trim(value: *const c_char) -> *const c_char {
+    new_str(str_ref(value).trim())
+}
+
+extern "C" fn fbl_str_upper(value: *const c_char) -> *const c_char {
+    new_str(&str_ref(value).to_uppercase())
+}
+
+extern "C" fn fbl_str_lower(value: *const c_char) -> *const c_char {
+    new_str(&str_ref(value).to_lowercase())
+}
+
+extern "C" fn fbl_str_starts_with(value: *const c_char, prefix: *const c_char) -> i64 {
+    str_ref(value).starts_with(str_ref(prefix)) as i64
+}
+
+extern "C" fn fbl_str_repeat(value: *const c_char, count: i64) -> *const c_char {
+    let value = str_ref(value);
+    let count = match usize::try_from(count) {
+        Ok(count) => count,
+        Err(_) => {
+            set_error(format!("negative count {} in repeat()", count));
+            return std::ptr::null();
+        }
+    };
+    // `str::repeat()` panics if the length overflows, which can't unwind out of here.
+    let fits = value
+        .len()
+        .checked_mul(count)
+        .is_some_and(|len| len <= isize::MAX as usize);
+    if !fits {
+        set_error(format!("count {} in repeat() is too large", count));
+        return std::ptr::null();
+    }
+    new_str(&value.repeat(count))
+}
+
+extern "C" fn fbl_i64_to_string(value: i64) -> *const c_char {
+    new_str(&value.to_string())
+}
+
+extern "C" fn fbl_u64_to_string(value: u64) -> *const c_char {
+    new_str(&value.to_string())
+}
+
+extern "C" fn fbl_parse_int(value: *const c_char) -> i64 {
+    parse_int(str_ref(value), "malformed integer")
+}
+
+// -------------------------------------------------------------------------------------------------
+// Environment variables.  Values which aren't valid Unicode are converted lossily.
+
+extern "C" fn fbl_getenv(name: *const c_char) -> *const c_char {
+    std::env::var_os(str_ref(name))
+        .map_or(std::ptr::null(), |value| new_str(&value.to_string_lossy()))
+}
+
+extern "C" fn fbl_has_env(name: *const c_char) -> i64 {
+    std::env::var_os(str_ref(name)).is_some() as i64
+}
+
+// -------------------------------------------------------------------------------------------------
+// Arrays of strings are immutable and, like strings, leaked.
+
+type StrArray = Vec<*const c_char>;
+
+fn new_array(elems: &[String]) -> *const StrArray {
+    Box::into_raw(Box::new(elems.iter().map(|elem| new_str(elem)).collect()))
+}
+
+// Safety: all the array pointers passed from compiled code were returned by `new_array()`.
+fn array_ref<'a>(ptr: *const StrArray) -> &'a StrArray {
+    unsafe { &*ptr }
+}
+
+extern "C" fn fbl_array_len(value: *const StrArray) -> i64 {
+    array_ref(value).len() as i64
+}
+
+extern "C" fn fbl_array_get(value: *const StrArray, idx: i64) -> *const c_char {
+    let array = array_ref(value);
+    match usize::try_from(idx).ok().and_then(|idx| array.get(idx)) {
+        Some(elem) => *elem,
+        None => {
+            set_error(format!(
+                "array index {} out of range for length {}",
+                idx,
+                array.len()
+            ));
+            std::ptr::null()
+        }
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+// Files are opened with a mode of "r" to read, "w" to write, truncating the file first, or "a" to
+// append.  The compiled code only sees a handle, which is an index into the open files plus one,
+// so a handle is never 0.  The `fbl_path_*()` functions open a file by path just for the one
+// operation.  Any failure is a runtime error.
+
+struct OpenFile {
+    file: File,
+    path: String,
+    mode: FileMode,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum FileMode {
+    Read,
+    Write,
+    Append,
+}
+
+thread_local! {
+    static FILES: RefCell<Vec<Option<OpenFile>>> = const { RefCell::new(Vec::new()) };
+}
+
+fn open_file(path: &str, mode: &str) -> Result<OpenFile, String> {
+    let mut options = std::fs::OpenOptions::new();
+    let mode = match mode {
+        "r" => {
+            options.read(true);
+            FileMode::Read
+        }
+        "w" => {
+            options.write(true).create(true).truncate(true);
+            FileMode::Write
+        }
+        "a" => {
+            options.append(true).create(true);
+            FileMode::Append
+        }
+        _ => {
+            return Err(format!(
+                "bad file mode '{}', expecting 'r', 'w' or 'a'",
+                mode
+            ))
+        }
+    };
+    let file = options
+        .open(path)
+        .map_err(|err| format!("cannot open '{}': {}", path, err))?;
+    Ok(OpenFile {
+        file,
+        path: path.to_string(),
+        mode,
+    })
+}
+
+impl OpenFile {
+    fn read_all(&mut self) -> Result<String, String> {
+        if self.mode != FileMode::Read {
+            return Err(format!("'{}' is not open for reading", self.path));
+        }
+        let mut contents = String::new();
+        self.file
+            .read_to_string(&mut contents)
+            .map_err(|err| format!("cannot read '{}': {}", self.path, err))?;
+        if contents.contains('\0') {
+            return Err(format!(
+                "cannot read '{}': it contains a null byte",
+                self.path
+            ));
+        }
+        Ok(contents)
+    }
+
+    fn read_lines(&mut self) -> Result<Vec<String>, String> {
+        Ok(self.read_all()?.lines().map(str::to_string).collect())
+    }
+
+    fn write(&mut self, text: &str, append: bool) -> Result<(), String> {
+        if self.mode == FileMode::Read {
+            return Err(format!("'{}' is not open for writing", self.path));
+        }
+        if append {
+            self.file
+                .seek(SeekFrom::End(0))
+                .map_err(|err| format!("cannot write '{}': {}", self.path, err))?;
+        }
+        self.file
+            .write_all(text.as_bytes())
+            .map_err(|err| format!("cannot write '{}': {}", self.path, err))
+    }
+}
+
+// Run `f` with the file for `handle`, or set an error if it isn't open.
+fn with_file<T>(handle: i64, f: impl FnOnce(&mut OpenFile) -> Result<T, String>) -> Option<T> {
+    let result = FILES.with(|files| {
+        let mut files = files.borrow_mut();
+        let idx = handle
+            .checked_sub(1)
+            .and_then(|idx| usize::try_from(idx).ok())
+            .ok_or(())?;
+        files.get_mut(idx).and_then(Option::as_mut).ok_or(()).map(f)
+    });
+    match result {
+        Ok(Ok(value)) => Some(value),
+        Ok(Err(msg)) => {
+            set_error(msg);
+            None
+        }
+        Err(()) => {
+            set_error(format!("bad file handle {}", handle));
+            None
+        }
+    }
+}
+
+fn ok_or_error<T>(result: Result<T, String>) -> Option<T> {
+    result.map_err(set_error).ok()
+}
+
+extern "C" fn fbl_file_open(path: *const c_char, mode: *const c_char) -> i64 {
+    let file = match ok_or_error(open_file(str_ref(path), str_ref(mode))) {
+        Some(file) => file,
+        None => return 0,
+    };
+    FILES.with(|files| {
+        let mut files = files.borrow_mut();
+        let idx = match files.iter().position(Option::is_none) {
+            Some(idx) => idx,
+            None => {
+                files.push(None);
+                files.len() - 1
+            }
+        };
+        files[idx] = Some(file);
+        idx as i64 + 1
+    })
+}
+
+extern "C" fn fbl_file_close(handle: i64) -> i64 {
+    if with_file(handle, |_| Ok(())).is_some() {
+        FILES.with(|files| files.borrow_mut()[handle as usize - 1] = None);
+    }
+    0
+}
+
+extern "C" fn fbl_file_read_all(handle: i64) -> *const c_char {
+    with_file(handle, OpenFile::read_all).map_or(std::ptr::null(), |text| new_str(&text))
+}
+
+extern "C" fn fbl_file_read_lines(handle: i64) -> *const StrArray {
+    with_file(handle, OpenFile::read_lines).map_or(std::ptr::null(), |lines| new_array(&lines))
+}
+
+extern "C" fn fbl_file_write(handle: i64, text: *const c_char, append: i64) -> i64 {
+    with_file(handle, |file| file.write(str_ref(text), append != 0));
+    0
+}
+
+extern "C" fn fbl_path_read_all(path: *const c_char) -> *const c_char {
+    ok_or_error(open_file(str_ref(path), "r").and_then(|mut file| file.read_all()))
+        .map_or(std::ptr::null(), |text| new_str(&text))
+}
+
+extern "C" fn fbl_path_read_lines(path: *const c_char) -> *const StrArray {
+    ok_or_error(open_file(str_ref(path), "r").and_then(|mut file| file.read_lines()))
+        .map_or(std::ptr::null(), |lines| new_array(&lines))
+}
+
+extern "C" fn fbl_path_write(path: *const c_char, text: *const c_char, append: i64) -> i64 {
+    let mode = if append != 0 { "a" } else { "w" };
+    ok_or_error(
+        open_file(str_ref(path), mode).and_then(|mut file| file.write(str_ref(text), false)),
+    );
+    0
+}
+
+// -------------------------------------------------------------------------------------------------
+// The arguments for the script are set by `reset()` before it's run.  They're made into an array
+// just once, which every call to `args()` returns.
+
+thread_local! {
+    static SCRIPT_ARGS: Cell<*const StrArray> = const { Cell::new(std::ptr::null()) };
+}
+
+extern "C" fn fbl_args() -> *const StrArray {
+    SCRIPT_ARGS.with(Cell::get)
+}
+
+extern "C" fn fbl_argc() -> i64 {
+    array_ref(fbl_args()).len() as i64
+}
@@ -1,6 +1,6 @@
 mod common;
 
-use common::test_str;
+use common::{test_plain_str, test_str};
 
 #[test]
 fn test_print_simple_numbers() {
@@ -11,13 +11,32 @@ fn test_print_simple_numbers() {
     test_str(r#"print(555);"#, "555\n");
     test_str(r#"print(999);"#, "999\n");
 
-    // These are wrong, but the expected behaviour for now.
+    // The old padded printing only shows the last three digits.
     test_str(r#"print(1000);"#, "  0\n");
     test_str(r#"print(1001);"#, "  1\n");
     test_str(r#"print(10001);"#, "  1\n");
     test_str(r#"print(99999);"#, "999\n");
 }
 
+#[test]
+fn test_print_full_range() {
+    test_plain_str(r#"print(0);"#, "0\n");
+    test_plain_str(r#"print(7);"#, "7\n");
+    test_plain_str(r#"print(1000);"#, "1000\n");
+    test_plain_str(r#"print(0 - 42);"#, "-42\n");
+    test_plain_str(r#"print(9223372036854775807);"#, "9223372036854775807\n");
+    test_plain_str(
+        r#"x = 0 - 9223372036854775807 - 1; print(x);"#,
+        "-9223372036854775808\n",
+    );
+    test_plain_str(r#"x: u64 = (0 - 1) as u64; print(x);"#, "18446744073709551615\n");
+    test_plain_str(r#"x: i8 = 0 - 128; print(x);"#, "-128\n");
+    test_plain_str(
+        r#"for (i; 998, 1001) { print(i); }"#,
+        "998\n999\n1000\n1001\n",
+    );
+}
+
 #[test]
 fn test_print_strings() {
     test_str(r#"print("Hello World!");"#, "Hello World!\n");
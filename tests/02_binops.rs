@@ -1,6 +1,6 @@
 mod common;
 
-use common::test_str;
+use common::{test_str, wrap_in_ifelse};
 
 #[test]
 fn test_binops_eq() {
@@ -43,11 +43,3 @@ fn test_binops_combo() {
 
     test_str(&wrap_in_ifelse("15 % 3 == 0 && 15 % 5 == 0"), "True!\n");
 }
-
-fn wrap_in_ifelse(expr: &str) -> String {
-    let mut out_str = String::new();
-    out_str.push_str("if (");
-    out_str.push_str(expr);
-    out_str.push_str(r#") { print("True!"); } else { print("False!"); }"#);
-    out_str
-}
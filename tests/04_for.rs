@@ -1,6 +1,6 @@
 mod common;
 
-use common::test_str;
+use common::{test_plain_str, test_str};
 
 #[test]
 fn test_for() {
@@ -10,6 +10,15 @@ fn test_for() {
     test_str(NO_ITER_CODE, "");
 }
 
+#[test]
+fn test_for_to_max() {
+    // The loop ends at i64::MAX rather than overflowing back to the start.
+    test_plain_str(
+        "for (i; 9223372036854775806, 9223372036854775807) { print(i); } print(0);",
+        "9223372036854775806\n9223372036854775807\n0\n",
+    );
+}
+
 const SMALL_LOOP_CODE: &str = r#"
 for (thing; 0, 4) {
     print(thing);
@@ -0,0 +1,44 @@
+mod common;
+
+use common::test_str;
+
+#[test]
+fn test_tuple_destructure() {
+    test_str(DESTRUCTURE_CODE, "  1\n  2\n");
+    test_str(DIVMOD_CODE, " 14\n  2\n");
+    test_str(SWAP_CODE, "  7\n  3\n");
+}
+
+#[test]
+fn test_tuple_variables() {
+    test_str(TUPLE_VAR_CODE, " 20\n 10\n");
+}
+
+const DESTRUCTURE_CODE: &str = r#"
+(a, b) = (1, 2);
+print(a);
+print(b);
+"#;
+
+const DIVMOD_CODE: &str = r#"
+(q, r) = divmod(100, 7);
+print(q);
+print(r);
+"#;
+
+const SWAP_CODE: &str = r#"
+a = 3;
+b = 7;
+(a, b) = (b, a);
+print(a);
+print(b);
+"#;
+
+const TUPLE_VAR_CODE: &str = r#"
+pair = (10, 20);
+(x, y) = pair;
+pair = (y, x);
+(x, y) = pair;
+print(x);
+print(y);
+"#;
@@ -0,0 +1,157 @@
+mod common;
+
+use common::{test_args_fail, test_str};
+
+#[test]
+fn test_closure_calls() {
+    test_str(SIMPLE_CLOSURE_CODE, "  1\n  0\n");
+    test_str(BLOCK_CLOSURE_CODE, "  0\n  3\n  5\n 15\n");
+    test_str(NO_PARAMS_CODE, " 42\n");
+}
+
+#[test]
+fn test_closure_captures() {
+    test_str(CAPTURE_CODE, "  1\n  0\n");
+    test_str(CAPTURE_TUPLE_CODE, "  2\n");
+    test_str(CAPTURE_BY_VALUE_CODE, "  5\n");
+}
+
+#[test]
+fn test_closure_args() {
+    test_str(PASS_CLOSURE_CODE, "Fizz\n  7\n");
+}
+
+#[test]
+fn test_higher_order_closures() {
+    test_str(PASS_ON_CLOSURE_CODE, "  4\n");
+    test_str(ALIAS_CLOSURE_CODE, "  8\n");
+    test_str(RETURN_CLOSURE_CODE, "  1\n  3\n");
+}
+
+#[test]
+fn test_closure_arg_errors() {
+    test_args_fail(
+        &["-e", "f = |x| x + 1; print(f(\"hi\"));"],
+        "Strings cannot be passed to closures.",
+    );
+    test_args_fail(
+        &["-e", "f = |x| x * 2; print(f(10n));"],
+        "Bigints cannot be passed to closures.",
+    );
+    test_args_fail(
+        &["-e", "f = |x| x; print(f(args()));"],
+        "Only ints and closures may be passed to closures",
+    );
+    test_args_fail(
+        &["-e", "apply = |f, x| f(x); print(apply(5, 5));"],
+        "Closure argument 1 must be a closure, found an int.",
+    );
+    test_args_fail(
+        &["-e", "apply = |f, x| f(x); print(apply(|x| x, |y| y));"],
+        "Closure argument 2 must be an int, found a closure.",
+    );
+    test_args_fail(
+        &["-e", "apply = |f, x| f(x); print(apply(|x| x));"],
+        "Closure takes 2 arguments, not 1.",
+    );
+    test_args_fail(
+        &[
+            "-e",
+            "f = |x| { if (x) { return |y| y; } else { return 1; } }; print(f(1));",
+        ],
+        "Expecting a closure to be returned",
+    );
+    test_args_fail(
+        &[
+            "-e",
+            "maybe = |n| { if (n > 0) { return |x| x; } else {} }; f = maybe(0); print(f(1));",
+        ],
+        "Runtime error on line 1: call of a null closure.",
+    );
+}
+
+const SIMPLE_CLOSURE_CODE: &str = r#"
+is_mult3 = |x| x % 3 == 0;
+print(is_mult3(9));
+print(is_mult3(10));
+"#;
+
+const BLOCK_CLOSURE_CODE: &str = r#"
+kind = |i| {
+  if (i % 15 == 0) {
+    return 15;
+  } else {
+    if (i % 5 == 0) { return 5; } else {}
+    if (i % 3 == 0) { return 3; } else {}
+  }
+};
+print(kind(7));
+print(kind(9));
+print(kind(10));
+print(kind(30));
+"#;
+
+const NO_PARAMS_CODE: &str = r#"
+answer = || 42;
+print(answer());
+"#;
+
+const CAPTURE_CODE: &str = r#"
+five = 5;
+is_mult5 = |x| x % five == 0;
+print(is_mult5(25));
+print(is_mult5(26));
+"#;
+
+const CAPTURE_TUPLE_CODE: &str = r#"
+pair = (1, 2);
+second = || {
+  (a, b) = pair;
+  return b;
+};
+print(second());
+"#;
+
+const CAPTURE_BY_VALUE_CODE: &str = r#"
+n = 5;
+get_n = || n;
+n = 6;
+print(get_n());
+"#;
+
+const PASS_CLOSURE_CODE: &str = r#"
+is_fizz = |x| x % 3 == 0;
+show = |pred, x| {
+  if (pred(x)) {
+    print("Fizz");
+  } else {
+    print(x);
+  }
+};
+show(is_fizz, 6);
+show(is_fizz, 7);
+"#;
+
+const PASS_ON_CLOSURE_CODE: &str = r#"
+apply = |f, x| f(x);
+twice = |g, x| apply(g, apply(g, x));
+last_digit = |x| x % 10;
+print(twice(last_digit, 1234));
+"#;
+
+const ALIAS_CLOSURE_CODE: &str = r#"
+wrap = |f| {
+  g = f;
+  return g(38);
+};
+print(wrap(|x| x % 10));
+"#;
+
+const RETURN_CLOSURE_CODE: &str = r#"
+make_mod = |n| |x| x % n;
+mod3 = make_mod(3);
+print(mod3(7));
+compose = |f, g| |x| f(g(x));
+h = compose(|x| x % 4, |x| x % 10);
+print(h(27));
+"#;
@@ -0,0 +1,49 @@
+mod common;
+
+use common::{run_full, test_args_fail, test_file, test_str};
+
+#[test]
+fn test_import() {
+    test_file("tests/imports/fizzbuzz.fbl", "  1\n  2\nFizz\n  4\nBuzz\n");
+    test_str(
+        r#"import "tests/imports/rules.fbl"; print(is_buzz(10));"#,
+        "  1\n",
+    );
+}
+
+#[test]
+fn test_import_once() {
+    test_file("tests/imports/diamond.fbl", "Common\nLeft\nRight\nDone\n");
+}
+
+#[test]
+fn test_import_errors() {
+    test_args_fail(&["tests/imports/cycle_a.fbl"], "Import cycle");
+    test_args_fail(&["-e", r#"import "no_such_file.fbl";"#], "no_such_file.fbl");
+}
+
+#[test]
+fn test_import_error_locations() {
+    // Errors in imported files name the file, while those in the main program only have the line.
+    let import = "import \"tests/imports/checks.fbl\";\n";
+    for (code, status, expected_err) in [
+        (
+            "print(ratio(1, 0));",
+            2,
+            "Runtime error on line 6 of tests/imports/checks.fbl: division by zero in `/`.\n",
+        ),
+        (
+            "print(halve(3));",
+            3,
+            "Assertion failed on line 3 of tests/imports/checks.fbl: odd (`x % 2 == 0`).\n",
+        ),
+        (
+            "x = 0;\nprint(halve(2) / x);",
+            2,
+            "Runtime error on line 3: division by zero in `/`.\n",
+        ),
+    ] {
+        let (code_status, _, stderr) = run_full(&format!("{}{}", import, code), &[]);
+        assert_eq!((code_status, stderr.as_str()), (Some(status), expected_err));
+    }
+}
@@ -0,0 +1,91 @@
+mod common;
+
+use common::{test_args_fail, test_str};
+
+#[test]
+fn test_switch() {
+    test_str(&wrap_in_switch(0), "Zero\n");
+    test_str(&wrap_in_switch(1), "One or two\n");
+    test_str(&wrap_in_switch(2), "One or two\n");
+    test_str(&wrap_in_switch(3), "Other\n");
+    test_str(&wrap_in_switch(1000), "Thousand\n");
+}
+
+#[test]
+fn test_switch_no_default() {
+    test_str(
+        r#"switch (3) { case 1: print("One"); } print("Done");"#,
+        "Done\n",
+    );
+}
+
+#[test]
+fn test_switch_negative_cases() {
+    test_str(
+        r#"for (i; 0 - 2, 2) {
+             switch (i) {
+               case -2: print("Minus two");
+               case -1, 1: print("One off");
+               default: print(i);
+             }
+           }"#,
+        "Minus two\nOne off\n  0\nOne off\n  2\n",
+    );
+    test_str(
+        r#"x: i8 = 0 - 128; switch (x) { case -128: print("Min"); case 127: print("Max"); }"#,
+        "Min\n",
+    );
+    test_args_fail(
+        &["-e", "x: u8 = 1; switch (x) { case -1: print(x); }"],
+        "Switch case value -1 is out of range for u8.",
+    );
+}
+
+#[test]
+fn test_switch_fizzbuzz() {
+    test_str(FIZZ_BUZZ_CODE, FIZZ_BUZZ_OUTPUT);
+}
+
+fn wrap_in_switch(value: i64) -> String {
+    format!(
+        r#"switch ({}) {{
+             case 0: print("Zero");
+             case 1, 2: print("One or two");
+             case 1000: print("Thousand");
+             default: print("Other");
+           }}"#,
+        value
+    )
+}
+
+const FIZZ_BUZZ_CODE: &str = r#"
+for (i; 1, 15) {
+  switch (i % 15) {
+    case 0:
+      print("FizzBuzz");
+    case 3, 6, 9, 12:
+      print("Fizz");
+    case 5, 10:
+      print("Buzz");
+    default:
+      print(i);
+  }
+}
+"#;
+
+const FIZZ_BUZZ_OUTPUT: &str = r#"  1
+  2
+Fizz
+  4
+Buzz
+Fizz
+  7
+  8
+Fizz
+Buzz
+ 11
+Fizz
+ 13
+ 14
+FizzBuzz
+"#;
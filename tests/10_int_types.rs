@@ -0,0 +1,85 @@
+mod common;
+
+use common::{test_args_fail, test_plain_str, test_str, wrap_in_ifelse};
+
+#[test]
+fn test_int_64_bit() {
+    test_str(&wrap_in_ifelse("5000000000 / 1000000 == 5000"), "True!\n");
+    test_str(&wrap_in_ifelse("4000000000 * 4 == 16000000000"), "True!\n");
+    test_str(&wrap_in_ifelse("9223372036854775807 > 0"), "True!\n");
+}
+
+#[test]
+fn test_int_arithmetic() {
+    test_str(&wrap_in_ifelse("2 + 3 * 4 == 14"), "True!\n");
+    test_str(&wrap_in_ifelse("(2 + 3) * 4 == 20"), "True!\n");
+    test_str(&wrap_in_ifelse("10 - 4 - 3 == 3"), "True!\n");
+    test_str(&wrap_in_ifelse("17 / 5 == 3"), "True!\n");
+    test_str(&wrap_in_ifelse("3 != 4"), "True!\n");
+    test_str(&wrap_in_ifelse("3 <= 3 && 3 >= 3"), "True!\n");
+    test_str(&wrap_in_ifelse("4 < 3"), "False!\n");
+    test_str(&wrap_in_ifelse("4 > 3"), "True!\n");
+}
+
+#[test]
+fn test_int_signedness() {
+    test_str(&wrap_in_ifelse("0 - 1 < 0"), "True!\n");
+    test_str(&wrap_in_ifelse("(0 - 7) % 3 == 0 - 1"), "True!\n");
+    test_str(&wrap_in_ifelse("(0 - 7) / 2 == 0 - 3"), "True!\n");
+    test_str(&wrap_in_ifelse("(0 - 1) as u64 > 0"), "True!\n");
+    test_str(&wrap_in_ifelse("((0 - 7) as u8) % 3 == 0"), "True!\n");
+}
+
+#[test]
+fn test_int_sized_types() {
+    test_str("x: u8 = 250; x = x + 10; print(x);", "  4\n");
+    test_str("x: i8 = 127; x = x + 1; print(x as u8);", "128\n");
+    test_str("x: i8 = 0 - 7; y = x as i64; print(y + 10);", "  3\n");
+    test_str("x: u8 = (0 - 7) as u8; y = x as i64; print(y);", "249\n");
+    test_str("x: u16 = 65535; print(x as u8);", "255\n");
+    test_str("x: i16 = 300; print(x as u8);", " 44\n");
+}
+
+#[test]
+fn test_int_type_errors() {
+    test_args_fail(&["-e", "x: u8 = 256;"], "out of range for u8");
+    test_args_fail(&["-e", "x: u8 = 1; y: i16 = 2; z = x + y;"], "use `as`");
+    test_args_fail(&["-e", "x: u8 = 1; x = x as i64;"], "use `as`");
+    // Arithmetic on literals is range checked too.
+    test_args_fail(
+        &["-e", "x: u8 = 300 - 1;"],
+        "Constant 299 is out of range for u8.",
+    );
+    test_args_fail(
+        &["-e", "x: u8 = 0 - 7;"],
+        "Constant -7 is out of range for u8.",
+    );
+    test_args_fail(
+        &["-e", "x: u8 = 1; x = x + (0 - 1);"],
+        "out of range for u8",
+    );
+    // Literals past i64 are only allowed as u64.
+    test_args_fail(
+        &["-e", "print(9223372036854775808);"],
+        "Literal 9223372036854775808 is out of range for i64.",
+    );
+    test_args_fail(&["-e", "x: u64 = 18446744073709551616;"], "u64 range");
+    // `<<` is multiplication, as it is at runtime.
+    test_args_fail(
+        &["-e", "x: i64 = 1 << 63;"],
+        "Constant 9223372036854775808 is out of range for i64.",
+    );
+}
+
+#[test]
+fn test_int_constants() {
+    test_str("x: u8 = 200 + 55; print(x);", "255\n");
+    test_str("x: i8 = 0 - 128; print(x as u8);", "128\n");
+    test_str("x: u8 = (1 << 8) - 1; print(x);", "255\n");
+    test_plain_str("x: u64 = 1 << 63; print(x);", "9223372036854775808\n");
+    test_plain_str("x: u8 = max(300, 7) - 100; print(x);", "200\n");
+    test_plain_str(
+        "x: u64 = 18446744073709551615; print(x, x == 18446744073709551615);",
+        "18446744073709551615 1\n",
+    );
+}
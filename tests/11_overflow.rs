@@ -0,0 +1,101 @@
+mod common;
+
+use common::{test_args, test_args_fail};
+
+#[test]
+fn test_overflow_wrap() {
+    test_mode("wrap", "x: u8 = 250; x = x + 10; print(x);", "  4\n");
+    test_mode("wrap", "x: u8 = 5; x = x - 10; print(x);", "251\n");
+    test_mode("wrap", "x: i8 = 100; x = x * 2; print(x as u8);", "200\n");
+    test_mode(
+        "wrap",
+        "x = 9223372036854775807; print(x + 1 < 0);",
+        "  1\n",
+    );
+    test_mode(
+        "wrap",
+        "x: i8 = 0 - 128; y: i8 = 0 - 1; print((x / y) as u8);",
+        "128\n",
+    );
+
+    test_mode("wrap", "x: u8 = 3; print(x << 7);", "128\n");
+
+    // Wrapping is the default.
+    test_args(&["-e", "x: u8 = 255; print(x + 1);"], "  0\n");
+}
+
+#[test]
+fn test_overflow_saturate() {
+    test_mode("saturate", "x: u8 = 250; x = x + 10; print(x);", "255\n");
+    test_mode("saturate", "x: u8 = 5; x = x - 10; print(x);", "  0\n");
+    test_mode("saturate", "x: i8 = 100; x = x * 2; print(x);", "127\n");
+    test_mode(
+        "saturate",
+        "x: i8 = 100; x = x * (0 - 2); print(x as u8);",
+        "128\n",
+    );
+    test_mode(
+        "saturate",
+        "x: i8 = 0 - 100; x = x - 100; print(x as u8);",
+        "128\n",
+    );
+    test_mode(
+        "saturate",
+        "x = 9223372036854775807; print(x + 1 > 0);",
+        "  1\n",
+    );
+    test_mode(
+        "saturate",
+        "x: i8 = 0 - 128; y: i8 = 0 - 1; print(x / y);",
+        "127\n",
+    );
+    test_mode("saturate", "x: u8 = 3; print(x << 7);", "255\n");
+    test_mode(
+        "saturate",
+        "x: i8 = 0 - 1; n = 8; print((x << n) as u8);",
+        "128\n",
+    );
+}
+
+#[test]
+fn test_overflow_trap() {
+    test_mode("trap", "x: u8 = 250; x = x + 5; print(x);", "255\n");
+    test_trap(
+        "x: u8 = 250;\nx = x + 10;",
+        "Runtime error on line 2: integer overflow in `+`.",
+    );
+    test_trap(
+        "x: u8 = 5;\n\nx = x - 10;",
+        "Runtime error on line 3: integer overflow in `-`.",
+    );
+    test_trap(
+        "x: i8 = 100; x = x * 2;",
+        "Runtime error on line 1: integer overflow in `*`.",
+    );
+    test_trap(
+        "x = 9223372036854775807;\nif (1) {\n  y = x + 1;\n} else {}",
+        "Runtime error on line 3: integer overflow in `+`.",
+    );
+    test_trap(
+        "x: i8 = 0 - 128; y: i8 = 0 - 1; z = x / y;",
+        "integer overflow in `/`",
+    );
+    test_trap(
+        "f = |x| x * x;\nprint(f(4294967296));",
+        "on line 1: integer overflow in `*`",
+    );
+    test_trap(
+        "x = 1;\ny = x << 64;",
+        "Runtime error on line 2: integer overflow in `<<`.",
+    );
+    test_trap("x = 1; y = x << 63;", "integer overflow in `<<`");
+    test_mode("trap", "x = 0 - 1; print((x << 63) < 0);", "  1\n");
+}
+
+fn test_mode(mode: &str, input: &str, expected: &str) {
+    test_args(&["--overflow", mode, "-e", input], expected);
+}
+
+fn test_trap(input: &str, expected_err: &str) {
+    test_args_fail(&["--overflow=trap", "-e", input], expected_err);
+}
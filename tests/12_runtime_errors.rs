@@ -0,0 +1,50 @@
+mod common;
+
+use common::{run_full, test_str};
+
+#[test]
+fn test_division_by_zero() {
+    test_runtime_error(
+        "print(10 % 0);",
+        "Runtime error on line 1: division by zero in `%`.",
+    );
+    test_runtime_error(
+        "x = 0;\nprint(10 / x);",
+        "Runtime error on line 2: division by zero in `/`.",
+    );
+    test_runtime_error(
+        "for (i; 0, 3) {\n  print(6 % (2 - i));\n}",
+        "Runtime error on line 2: division by zero in `%`.",
+    );
+    test_runtime_error("(q, r) = divmod(5, 0);", "division by zero in `/`");
+    test_runtime_error("x: u8 = 0; print(5 % x);", "division by zero in `%`");
+}
+
+#[test]
+fn test_division_ok() {
+    test_str("x: i8 = 0 - 100; print((x / 3) as u8);", "223\n");
+    test_str("x: u8 = 200; print(x / 3);", " 66\n");
+    test_str("x = 7; y = 2; print(x / y);", "  3\n");
+}
+
+#[test]
+fn test_abort() {
+    test_runtime_error("abort();", "Runtime error on line 1: abort() called.");
+    test_runtime_error(
+        "if (1) {\n  abort(\"Bad thing\");\n} else {}",
+        "Runtime error on line 2: Bad thing.",
+    );
+}
+
+#[test]
+fn test_output_before_error() {
+    // Output up to the error is still flushed.
+    let (_, stdout, _) = run_full("print(1); print(2); abort();", &[]);
+    assert_eq!(stdout, "1\n2\n");
+}
+
+fn test_runtime_error(input: &str, expected_err: &str) {
+    let (status, _, stderr) = run_full(input, &[]);
+    assert_eq!(status, Some(2), "{}", stderr);
+    assert!(stderr.contains(expected_err), "{}", stderr);
+}
@@ -0,0 +1,67 @@
+mod common;
+
+use common::{test_plain_str, test_str, wrap_in_ifelse};
+
+#[test]
+fn test_bitwise_ops() {
+    test_str(&wrap_in_ifelse("12 & 10 == 8"), "True!\n");
+    test_str(&wrap_in_ifelse("12 | 10 == 14"), "True!\n");
+    test_str(&wrap_in_ifelse("12 ^ 10 == 6"), "True!\n");
+    test_str(&wrap_in_ifelse("~0 == 0 - 1"), "True!\n");
+    test_str(&wrap_in_ifelse("~5 & 7 == 2"), "True!\n");
+    test_str("x: u8 = 0; print(~x);", "255\n");
+}
+
+#[test]
+fn test_shift_ops() {
+    test_str(&wrap_in_ifelse("1 << 10 == 1024"), "True!\n");
+    test_str(&wrap_in_ifelse("1024 >> 3 == 128"), "True!\n");
+    test_str(&wrap_in_ifelse("(0 - 16) >> 2 == 0 - 4"), "True!\n");
+    test_str(&wrap_in_ifelse("(0 - 1) >>> 60 == 15"), "True!\n");
+    test_str("x: u8 = 255; print(x >>> 4);", " 15\n");
+    test_str("x: u8 = 240; print(x >> 4);", "255\n");
+    test_str("x: u8 = 1; n: u8 = 7; print(x << n);", "128\n");
+}
+
+#[test]
+fn test_wide_shifts() {
+    // Shifting by the width of the type or more shifts out every bit, rather than masking the
+    // amount.
+    test_plain_str(
+        "x = 1; n = 64; print(x << n, x >> n, (0 - 1) >> n, (0 - 1) >>> n, x << (0 - 1));",
+        "0 0 -1 0 0\n",
+    );
+    test_str("x: u8 = 1; print(x << 8, x << 300);", "0 0\n");
+    test_str("x: i8 = 0 - 128; print((x >> 8) as u8);", "255\n");
+    test_plain_str(
+        "x: u8 = 255 >>> 64; y: i8 = (0 - 1) >> 70; print(x, y);",
+        "0 -1\n",
+    );
+}
+
+#[test]
+fn test_bitwise_precedence() {
+    test_str(&wrap_in_ifelse("1 | 2 ^ 3 & 4 == 3"), "True!\n");
+    test_str(&wrap_in_ifelse("1 + 1 << 2 == 8"), "True!\n");
+    test_str(&wrap_in_ifelse("6 & 3 && 1"), "True!\n");
+    test_str(&wrap_in_ifelse("2 < 1 << 2"), "True!\n");
+}
+
+#[test]
+fn test_bitmask_fizzbuzz() {
+    test_str(BITMASK_CODE, "  1\n  2\nFizz\n  4\nBuzz\nFizz\n");
+}
+
+const BITMASK_CODE: &str = r#"
+for (i; 1, 6) {
+  mask = 0;
+  if (i % 3 == 0) { mask = mask | 1; } else {}
+  if (i % 5 == 0) { mask = mask | 2; } else {}
+  switch (mask) {
+    case 1: print("Fizz");
+    case 2: print("Buzz");
+    case 3: print("FizzBuzz");
+    default: print(i);
+  }
+}
+"#;
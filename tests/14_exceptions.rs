@@ -0,0 +1,83 @@
+mod common;
+
+use common::{test_args, test_args_fail, test_str};
+
+#[test]
+fn test_throw_catch() {
+    test_str(
+        r#"try { print(1); throw "bad"; print(2); } catch (e) { print(e); } print(3);"#,
+        "  1\nbad\n  3\n",
+    );
+    test_str(
+        r#"try { print(1); } catch (e) { print(e); } print(2);"#,
+        "  1\n  2\n",
+    );
+}
+
+#[test]
+fn test_finally() {
+    test_str(
+        r#"try { throw "bad"; } catch (e) { print(e); } finally { print("done"); }"#,
+        "bad\ndone\n",
+    );
+    test_str(r#"try { print(1); } finally { print(2); }"#, "  1\n  2\n");
+    test_str(
+        r#"try { try { throw "inner"; } finally { print("finally"); } } catch (e) { print(e); }"#,
+        "finally\ninner\n",
+    );
+    test_str(
+        r#"try {
+             try { throw "first"; } catch (e) { throw "second"; } finally { print("finally"); }
+           } catch (e) {
+             print(e);
+           }"#,
+        "finally\nsecond\n",
+    );
+}
+
+#[test]
+fn test_closures() {
+    test_str(
+        r#"f = |x| { if (x > 2) { throw "too big"; } else {} return x * 2; };
+           try { print(f(1)); print(f(5)); print(9); } catch (e) { print(e); }"#,
+        "  2\ntoo big\n",
+    );
+    test_str(
+        r#"f = |x| { try { return 10 / x; } finally { print("finally"); } };
+           try { print(f(5)); print(f(0)); } catch (e) { print(e); }"#,
+        "finally\n  2\nfinally\ndivision by zero in `/`\n",
+    );
+}
+
+#[test]
+fn test_runtime_errors() {
+    test_str(
+        "x = 0; try { print(5 % x); } catch (e) { print(e); }",
+        "division by zero in `%`\n",
+    );
+    test_args(
+        &[
+            "--overflow",
+            "trap",
+            "-e",
+            "x: u8 = 255; try { x = x + 1; } catch (e) { print(e); }",
+        ],
+        "integer overflow in `+`\n",
+    );
+}
+
+#[test]
+fn test_uncaught() {
+    test_args_fail(
+        &["-e", "print(1);\nthrow \"oops\";"],
+        "Runtime error on line 2: oops.",
+    );
+    test_args_fail(
+        &["-e", "f = || { throw \"deep\"; };\n\nf();"],
+        "Runtime error on line 1: deep.",
+    );
+    test_args_fail(
+        &["-e", "try { throw \"again\"; } catch (e) { throw e; }"],
+        "Runtime error on line 1: again.",
+    );
+}
@@ -0,0 +1,59 @@
+mod common;
+
+use common::{test_args_fail, test_str};
+
+#[test]
+fn test_literals() {
+    test_str(
+        "print(123456789012345678901234567890n);",
+        "123456789012345678901234567890\n",
+    );
+    test_str("print(0n - 5n);", "-5\n");
+    test_str("x: bigint = 7; print(x);", "7\n");
+}
+
+#[test]
+fn test_arithmetic() {
+    test_str(
+        "f = 1n; for (i; 1, 30) { f = f * i; } print(f);",
+        "265252859812191058636308480000000\n",
+    );
+    test_str(
+        "a = 0n; b = 1n; for (i; 1, 100) { (a, b) = (b, a + b); } print(a);",
+        "354224848179261915075\n",
+    );
+    test_str(
+        "x = 100000000000000000000n; print(x / 7); print(x % 7); print((0n - x) % 7);",
+        "14285714285714285714\n2\n-2\n",
+    );
+    test_str(
+        "x: u64 = (0 - 1) as u64; print(x as bigint + 1);",
+        "18446744073709551616\n",
+    );
+}
+
+#[test]
+fn test_comparison() {
+    test_str(
+        "x = 100000000000000000000n; print(x > 99999999999999999999n); print(x == x + 0); print(1n < 0);",
+        "  1\n  1\n  0\n",
+    );
+}
+
+#[test]
+fn test_conversion() {
+    test_str("x = 4294967298n; print((x as u32) as i64);", "  2\n");
+    test_str("print(((0n - 1n) as i64) == 0 - 1);", "  1\n");
+}
+
+#[test]
+fn test_division_by_zero() {
+    test_str(
+        "try { print(5n / 0n); } catch (e) { print(e); }",
+        "division by zero in `/`\n",
+    );
+    test_args_fail(
+        &["-e", "print(5n % 0);"],
+        "Runtime error on line 1: division by zero in `%`.",
+    );
+}
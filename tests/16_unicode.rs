@@ -0,0 +1,35 @@
+mod common;
+
+use common::{test_args_fail, test_str};
+
+#[test]
+fn test_identifiers() {
+    test_str("größe = 3; print(größe);", "  3\n");
+    test_str("_név = 4; 日本 = 5; print(_név + 日本);", "  9\n");
+    test_str("x_2 = 6; print(x_2);", "  6\n");
+}
+
+#[test]
+fn test_len() {
+    test_str(r#"print(len("hello"));"#, "  5\n");
+    test_str(r#"print(len("héllo wörld"));"#, " 11\n");
+    test_str(r#"s = "日本語"; print(len(s));"#, "  3\n");
+    test_str(r#"print(len(""));"#, "  0\n");
+}
+
+#[test]
+fn test_index() {
+    test_str(r#"s = "héllo"; print(s[1]); print(s[4]);"#, "é\no\n");
+    test_str(
+        r#"s = "日本語"; for (i; 0, 2) { print(s[i]); }"#,
+        "日\n本\n語\n",
+    );
+    test_str(
+        r#"s = "abc"; try { print(s[3]); } catch (e) { print(e); }"#,
+        "string index out of range\n",
+    );
+    test_args_fail(
+        &["-e", "s = \"abc\";\nprint(s[0 - 1]);"],
+        "Runtime error on line 2: string index out of range.",
+    );
+}
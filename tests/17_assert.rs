@@ -0,0 +1,42 @@
+mod common;
+
+use common::{run_full, test_str};
+
+#[test]
+fn test_assert_passes() {
+    test_str("x = 5; assert(x < 10); print(x);", "  5\n");
+    test_str(
+        r#"x = 5; assert(x == 5, "x should be five"); print(x);"#,
+        "  5\n",
+    );
+}
+
+#[test]
+fn test_assert_fails() {
+    test_assert_failure(
+        "x = 5;\nassert(x > 10 && x != 0 );",
+        "Assertion failed on line 2: `x > 10 && x != 0`.",
+    );
+    test_assert_failure(
+        "x = 5;\n\nassert(x == 4, \"x must be four\");",
+        "Assertion failed on line 3: x must be four (`x == 4`).",
+    );
+    test_assert_failure(
+        "f = |n| { assert(n % 2 == 0, \"odd\"); return n / 2; };\nprint(f(3));",
+        "Assertion failed on line 1: odd (`n % 2 == 0`).",
+    );
+}
+
+#[test]
+fn test_assert_not_catchable() {
+    test_assert_failure(
+        "try { assert(0); } catch (e) { print(e); }",
+        "Assertion failed on line 1: `0`.",
+    );
+}
+
+fn test_assert_failure(input: &str, expected_err: &str) {
+    let (status, _, stderr) = run_full(input, &[]);
+    assert_eq!(status, Some(3), "{}", stderr);
+    assert!(stderr.contains(expected_err), "{}", stderr);
+}
@@ -0,0 +1,38 @@
+mod common;
+
+use common::{test_args_status, test_str};
+
+#[test]
+fn test_exit() {
+    test_args_status(&["-e", "print(1); exit(4); print(2);"], "  1\n", 4);
+    test_args_status(&["-e", "x: u8 = 9; exit(x);"], "", 9);
+    test_args_status(&["-e", "f = |n| { exit(n); }; f(3); print(1);"], "", 3);
+    test_args_status(
+        &[
+            "-e",
+            "f = |n| { exit(n); }; try { f(3); } catch (e) { print(e); } print(1);",
+        ],
+        "",
+        3,
+    );
+    test_args_status(
+        &[
+            "-e",
+            "for (i; 1, 10) { print(i); if (i == 2) { exit(0); } else {} }",
+        ],
+        "  1\n  2\n",
+        0,
+    );
+}
+
+#[test]
+fn test_top_level_return() {
+    test_args_status(&["-e", "print(1); return 7; print(2);"], "  1\n", 7);
+    test_args_status(
+        &["-e", "try { return 5; } finally { print(9); }"],
+        "  9\n",
+        5,
+    );
+    test_args_status(&["-e", "f = || 6; return f();"], "", 6);
+    test_str("print(1); return 0;", "  1\n");
+}
@@ -0,0 +1,81 @@
+mod common;
+
+use common::{test_args_fail, test_str};
+
+#[test]
+fn test_printf_basic() {
+    test_str(
+        r#"for (i; 1, 3) { printf("{:>3} {}", i, "word"); }"#,
+        "  1 word\n  2 word\n  3 word\n",
+    );
+    test_str(r#"printf("no args");"#, "no args\n");
+    test_str(r#"printf("{{}} {}", 1);"#, "{} 1\n");
+    test_str(r#"printf("{}{}", 0 - 5, "x");"#, "-5x\n");
+}
+
+#[test]
+fn test_printf_alignment() {
+    test_str(
+        r#"printf("[{:<5}] [{:^7}] [{:>4}] [{:6}] [{:6}]", "ab", "mid", "r", "s", 12);"#,
+        "[ab   ] [  mid  ] [   r] [s     ] [    12]\n",
+    );
+    test_str(
+        r#"printf("[{:*>6}] [{:-<4}] [{:é^5}]", 42, "ab", "x");"#,
+        "[****42] [ab--] [ééxéé]\n",
+    );
+    test_str(r#"printf("[{:>3}]", "wider");"#, "[wider]\n");
+}
+
+#[test]
+fn test_printf_numbers() {
+    test_str(
+        r#"printf("{:+} {:+} {:05} {:+06}", 7, 0 - 7, 0 - 42, 42);"#,
+        "+7 -7 -0042 +00042\n",
+    );
+    test_str(
+        r#"printf("{:x} {:X} {:o} {:b} {:08b}", 255, 255, 8, 5, 5);"#,
+        "ff FF 10 101 00000101\n",
+    );
+    test_str(
+        r#"x: i8 = 0 - 1; y: u64 = (0 - 1) as u64; printf("{:x} {} {}", x, x, y);"#,
+        "ff -1 18446744073709551615\n",
+    );
+    test_str(
+        r#"printf("{:>25} {:x}", 123456789012345678901n, 0n - 255n);"#,
+        "    123456789012345678901 -ff\n",
+    );
+}
+
+#[test]
+fn test_printf_other_values() {
+    test_str(r#"printf("{}", (1, "a", 2n));"#, "(1, a, 2)\n");
+    test_str(r#"f = |a| a; printf("{:>10}|", f);"#, " <closure>|\n");
+}
+
+#[test]
+fn test_print_many() {
+    test_str(r#"print(1, "two", 3n, (4, 5));"#, "1 two 3 (4, 5)\n");
+    test_str(r#"print();"#, "\n");
+    // A single tuple or closure is formatted the same way.
+    test_str(r#"t = (1, "a", (2, 3n)); print(t);"#, "(1, a, (2, 3))\n");
+    test_str(r#"f = |a| a; print(f);"#, "<closure>\n");
+}
+
+#[test]
+fn test_printf_errors() {
+    test_args_fail(
+        &["-e", r#"printf("{} {}", 1);"#],
+        "Format has 2 placeholders but 1 arguments were given.",
+    );
+    test_args_fail(
+        &["-e", r#"printf("{:q}", 1);"#],
+        "Bad printf() format: invalid format spec `{:q}`.",
+    );
+    test_args_fail(&["-e", r#"printf("{", 1);"#], "unterminated `{`");
+    test_args_fail(&["-e", r#"printf("}");"#], "unmatched `}`");
+    test_args_fail(&["-e", r#"printf("{:x}", "str");"#], "is only for numbers");
+    test_args_fail(
+        &["-e", r#"f = "{}"; printf(f, 1);"#],
+        "printf() requires a string literal format.",
+    );
+}
@@ -0,0 +1,25 @@
+mod common;
+
+use common::{test_args_status, test_plain_str, test_str};
+
+#[test]
+fn test_flush() {
+    test_str("print(1); flush(); print(2);", "  1\n  2\n");
+    test_str("flush();", "");
+}
+
+#[test]
+fn test_flushed_on_exit() {
+    test_args_status(&["-e", "print(1); exit(5);"], "  1\n", 5);
+    test_args_status(&["-e", "print(1); return 6;"], "  1\n", 6);
+    test_args_status(&["-e", "print(1); assert(0);"], "  1\n", 3);
+    test_args_status(&["-e", "print(1); x = 0; print(1 / x);"], "  1\n", 2);
+    test_args_status(&["-e", "print(1); throw \"oops\";"], "  1\n", 2);
+}
+
+#[test]
+fn test_large_output() {
+    // Much more than fits in the output buffer.
+    let expected = (1..=100000).map(|i| format!("{}\n", i)).collect::<String>();
+    test_plain_str("for (i; 1, 100000) { print(i); }", &expected);
+}
@@ -0,0 +1,28 @@
+mod common;
+
+use common::{run_full, test_plain_str, test_str};
+
+#[test]
+fn test_write() {
+    test_str(r#"write("a"); write("b"); print("c");"#, "abc\n");
+    test_plain_str(r#"for (i; 1, 3) { write(i, ""); } print();"#, "1 2 3 \n");
+    test_str(r#"write("no newline");"#, "no newline");
+    test_str(r#"write();"#, "");
+}
+
+#[test]
+fn test_stderr() {
+    test_output(
+        r#"print("out"); eprint("err", 1); ewrite("a"); ewrite("b"); write("x");"#,
+        "out\nx",
+        "err 1\nab",
+    );
+    test_output(r#"eprint();"#, "", "\n");
+}
+
+fn test_output(input: &str, expected_out: &str, expected_err: &str) {
+    assert_eq!(
+        run_full(input, &[]),
+        (Some(0), expected_out.to_string(), expected_err.to_string())
+    );
+}
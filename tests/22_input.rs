@@ -0,0 +1,43 @@
+mod common;
+
+use common::test_stdin;
+
+#[test]
+fn test_read_line() {
+    test_stdin(
+        "a = read_line(); b = read_line(); print(b); print(a);",
+        "first\nsecond\n",
+        "second\nfirst\n",
+    );
+    test_stdin(
+        "l = read_line(); print(l, len(l));",
+        "héllo wörld\r\n",
+        "héllo wörld 11\n",
+    );
+    test_stdin("l = read_line(); print(len(l));", "no newline", " 10\n");
+    test_stdin(
+        "a = read_line(); b = read_line(); print(is_null(a), is_null(b));",
+        "\n",
+        "0 1\n",
+    );
+}
+
+#[test]
+fn test_read_int() {
+    test_stdin("n = read_int(); print(n * 2);", "21\n", " 42\n");
+    test_stdin(
+        "n = read_int(); m = read_int(); print(n + m);",
+        "  -5 \n12\n",
+        "  7\n",
+    );
+    test_stdin(
+        "try { read_int(); } catch (e) { print(e); }",
+        "abc\n",
+        "malformed integer input 'abc'\n",
+    );
+    test_stdin(
+        "try { read_int(); } catch (e) { print(e); }",
+        "",
+        "end of input in read_int()\n",
+    );
+}
@@ -0,0 +1,67 @@
+mod common;
+
+use common::{test_args, test_args_fail, test_plain_str};
+
+#[test]
+fn test_no_args() {
+    test_plain_str("print(argc()); print(args());", "0\n[]\n");
+}
+
+#[test]
+fn test_args_array() {
+    test_args(
+        &[
+            "-e",
+            "print(argc(), args()); a = args(); print(a[0], len(a[1]));",
+            "--",
+            "x",
+            "yz",
+        ],
+        "2 [x, yz]\nx 2\n",
+    );
+    test_args(
+        &["-e", "printf(\"{:>3}|\", args());", "--", "a", "b"],
+        "[  a,   b]|\n",
+    );
+    test_args_fail(
+        &["-e", "print(args()[2]);", "--", "a", "b"],
+        "Runtime error on line 1: array index 2 out of range for length 2.",
+    );
+    test_args(
+        &[
+            "-e",
+            "try { print(args()[0 - 1]); } catch (e) { print(e); }",
+            "--",
+            "a",
+        ],
+        "array index -1 out of range for length 1\n",
+    );
+}
+
+#[test]
+fn test_parse_int() {
+    test_plain_str(
+        "print(parse_int(\"42\") + 1, parse_int(\" -7 \"));",
+        "43 -7\n",
+    );
+    test_plain_str(
+        "try { parse_int(\"4x\"); } catch (e) { print(e); }",
+        "malformed integer '4x'\n",
+    );
+}
+
+#[test]
+fn test_for_arg_bounds() {
+    let code = "for (i; parse_int(args()[0]), parse_int(args()[1])) { write(i, \"\"); } print();";
+    test_args(&["-e", code, "--", "3", "7"], "3 4 5 6 7 \n");
+    test_args(&["fizzbuzz.script", "--", "98", "100"], " 98\nFizz\nBuzz\n");
+}
+
+#[test]
+fn test_for_expr_bounds() {
+    // The bounds are evaluated once, before the loop.
+    test_plain_str(
+        "n = 2; for (i; n - 1, n * 2) { n = 10; write(i, \"\"); } print();",
+        "1 2 3 4 \n",
+    );
+}
@@ -0,0 +1,141 @@
+mod common;
+
+use common::{test_args, test_args_fail};
+
+use fizzbuzz::{GlobalValue, IntType, OverflowMode, RunError, RunOptions};
+
+#[test]
+fn test_define_globals() {
+    test_args(
+        &["-D", "n=3", "-D", "who=world", "-e", "print(n + 1, who);"],
+        "4 world\n",
+    );
+    test_args(
+        &[
+            "-D",
+            "first=4",
+            "-D",
+            "last=6",
+            "-e",
+            "for (i; first, last) { print(i); }",
+        ],
+        "  4\n  5\n  6\n",
+    );
+    // Globals are variables like any other.
+    test_args(
+        &[
+            "-D",
+            "n=1",
+            "-e",
+            "n = n + 1; f = || n * 10; print(f(), n);",
+        ],
+        "20 2\n",
+    );
+}
+
+#[test]
+fn test_typed_globals() {
+    test_args(&["-D", "s:str=12", "-e", "print(s, len(s));"], "12 2\n");
+    test_args(
+        &["-D", "b:u8=255", "-e", "print(b as u16 + 1, 0);"],
+        "256 0\n",
+    );
+    test_args_fail(
+        &["-D", "b:u8=255", "-e", "x: i64 = b;"],
+        "Expecting i64 but found u8",
+    );
+}
+
+#[test]
+fn test_bad_globals() {
+    test_args_fail(
+        &["-e", "print(limit);"],
+        "global `limit` is used but not defined",
+    );
+    test_args_fail(
+        &["-e", "f = |x| x; print(f(1), x);"],
+        "global `x` is used but not defined",
+    );
+    test_args_fail(
+        &["-e", "f = |x| x < limit; print(f(1));"],
+        "global `limit` is used but not defined",
+    );
+    test_args_fail(
+        &["-e", "f = |x| x; print(f(1), x);"],
+        "global `x` is used but not defined",
+    );
+    test_args_fail(&["-D", "x", "-e", "1;"], "must be `name=value`");
+    test_args_fail(
+        &["-D", "1x=2", "-e", "1;"],
+        "global name `1x` is not an identifier",
+    );
+    test_args_fail(
+        &["-D", "x:u8=256", "-e", "1;"],
+        "global `x` value `256` is not a valid u8",
+    );
+    test_args_fail(&["-D", "x:f32=1", "-e", "1;"], "unknown integer type `f32`");
+}
+
+#[test]
+fn test_globals_api() {
+    let options = RunOptions {
+        overflow: OverflowMode::Wrap,
+        padded_ints: false,
+        allow_env: true,
+        args: Vec::new(),
+    };
+    let dir = std::env::current_dir().unwrap();
+    let program = fizzbuzz::parse_program("return n * len(s);", &dir).unwrap();
+
+    let globals = vec![
+        ("n".to_string(), GlobalValue::Int(IntType::I64, 3)),
+        ("s".to_string(), GlobalValue::Str("abcd".to_string())),
+    ];
+    assert_eq!(fizzbuzz::run(&program, &globals, &options).unwrap(), 12);
+
+    match fizzbuzz::run(&program, &globals[..1], &options) {
+        Err(RunError::Compile(msg)) => assert_eq!(msg, "global `s` is used but not defined"),
+        result => panic!("Expecting a missing global error, got {:?}.", result),
+    }
+}
+
+// Failures are returned to the host rather than exiting, and each run starts afresh.
+#[test]
+fn test_run_results() {
+    let mut options = RunOptions {
+        overflow: OverflowMode::Trap,
+        padded_ints: false,
+        allow_env: true,
+        args: vec!["a".to_string(), "b".to_string()],
+    };
+    let dir = std::env::current_dir().unwrap();
+    let run = |code: &str, options: &RunOptions| {
+        let program = fizzbuzz::parse_program(code, &dir).unwrap();
+        fizzbuzz::run(&program, &[], options)
+    };
+
+    assert_eq!(run("return argc();", &options).unwrap(), 2);
+    assert_eq!(
+        run("f = |n| { exit(n); }; f(5); return 1;", &options).unwrap(),
+        5
+    );
+    match run("f = |x| x; f(\"a\");", &options) {
+        Err(RunError::Compile(msg)) => assert_eq!(msg, "Strings cannot be passed to closures."),
+        result => panic!("Expecting a compile error, got {:?}.", result),
+    }
+    match run("f = |n| 10 / n; f(0);", &options) {
+        Err(RunError::Runtime(msg)) => {
+            assert_eq!(msg, "Runtime error on line 1: division by zero in `/`.")
+        }
+        result => panic!("Expecting a runtime error, got {:?}.", result),
+    }
+    match run("assert(argc() == 0, \"no args\");", &options) {
+        Err(RunError::Assert(msg)) => {
+            assert_eq!(msg, "Assertion failed on line 1: no args (`argc() == 0`).")
+        }
+        result => panic!("Expecting an assertion failure, got {:?}.", result),
+    }
+
+    options.args.clear();
+    assert_eq!(run("assert(argc() == 0); return 3;", &options).unwrap(), 3);
+}
@@ -0,0 +1,61 @@
+mod common;
+
+use common::{run_full, run_full_env, test_args};
+
+#[test]
+fn test_getenv() {
+    test_env(
+        "print(getenv(\"FBL_TEST_VAR\"), has_env(\"FBL_TEST_VAR\"));",
+        "hello 1\n",
+    );
+    test_env(
+        "print(is_null(getenv(\"FBL_TEST_UNSET\")), has_env(\"FBL_TEST_UNSET\"));",
+        "1 0\n",
+    );
+}
+
+#[test]
+fn test_env_verbosity() {
+    test_env(
+        r#"
+verbose = 0;
+if (has_env("FBL_TEST_VAR")) {
+  verbose = len(getenv("FBL_TEST_VAR"));
+} else {}
+print(verbose);
+"#,
+        "5\n",
+    );
+}
+
+#[test]
+fn test_no_env() {
+    for name in ["getenv", "has_env"] {
+        let (status, stdout, stderr) = run_full(
+            &format!("print(1); print({}(\"HOME\"));", name),
+            &["--no-env"],
+        );
+        assert_eq!(status, Some(2));
+        assert_eq!(stdout, "1\n");
+        assert_eq!(
+            stderr,
+            format!(
+                "Runtime error on line 1: {}() is not allowed, environment access is disabled.\n",
+                name
+            )
+        );
+    }
+    test_args(
+        &[
+            "--no-env",
+            "-e",
+            "try { getenv(\"HOME\"); } catch (e) { print(e); }",
+        ],
+        "getenv() is not allowed, environment access is disabled\n",
+    );
+}
+
+fn test_env(input: &str, expected: &str) {
+    let (_, stdout, stderr) = run_full_env(input, &[], &[("FBL_TEST_VAR", "hello")]);
+    assert_eq!(stdout, expected, "{}", stderr);
+}
@@ -0,0 +1,95 @@
+mod common;
+
+use common::{test_args_fail, test_plain_str};
+
+// Each test has its own scratch directory, so they can run in parallel.
+fn scratch_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("fbl_files_{}_{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_write_and_read_handles() {
+    let dir = scratch_dir("handles");
+    let path = dir.join("report.txt");
+    let code = format!(
+        r#"
+path = "{}";
+f = open(path, "w");
+write_file(f, "one
+");
+write_file(f, "two
+");
+close(f);
+f = open(path, "a");
+append_file(f, "three");
+close(f);
+f = open(path, "r");
+lines = read_lines(f);
+close(f);
+print(len(lines), lines);
+"#,
+        path.display()
+    );
+    test_plain_str(&code, "3 [one, two, three]\n");
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "one\ntwo\nthree");
+}
+
+#[test]
+fn test_write_and_read_paths() {
+    let dir = scratch_dir("paths");
+    let path = dir.join("out.txt");
+    let code = format!(
+        r#"
+path = "{}";
+write_file(path, "old");
+write_file(path, "a");
+append_file(path, "b");
+print(read_all(path), len(read_lines(path)));
+"#,
+        path.display()
+    );
+    test_plain_str(&code, "ab 1\n");
+}
+
+#[test]
+fn test_file_errors() {
+    let dir = scratch_dir("errors");
+    let missing = dir.join("missing.txt");
+    let missing = missing.display();
+    test_plain_str(
+        &format!(
+            "try {{ read_all(\"{}\"); }} catch (e) {{ print(e); }}",
+            missing
+        ),
+        &format!(
+            "cannot open '{}': No such file or directory (os error 2)\n",
+            missing
+        ),
+    );
+    test_args_fail(
+        &["-e", &format!("f = open(\"{}\", \"r\");", missing)],
+        "Runtime error on line 1: cannot open",
+    );
+
+    let path = dir.join("file.txt");
+    let code = format!(
+        r#"
+f = open("{}", "w");
+try {{ read_all(f); }} catch (e) {{ print(e); }}
+close(f);
+try {{ close(f); }} catch (e) {{ print(e); }}
+try {{ open("x", "rw"); }} catch (e) {{ print(e); }}
+try {{ close(0 - 9223372036854775807 - 1); }} catch (e) {{ print(e); }}
+"#,
+        path.display()
+    );
+    test_plain_str(
+        &code,
+        &format!(
+            "'{}' is not open for reading\nbad file handle 1\nbad file mode 'rw', expecting 'r', 'w' or 'a'\nbad file handle -9223372036854775808\n",
+            path.display()
+        ),
+    );
+}
@@ -0,0 +1,91 @@
+mod common;
+
+use common::{test_args, test_args_fail, test_plain_str};
+
+#[test]
+fn test_inline_math() {
+    test_plain_str(
+        "print(abs(0 - 5), abs(7), sign(0 - 9), sign(0), sign(4));",
+        "5 7 -1 0 1\n",
+    );
+    test_plain_str(
+        "print(min(3, 0 - 2), max(3, 0 - 2), clamp(15, 0, 10), clamp(0 - 5, 0, 10), clamp(5, 0, 10));",
+        "-2 3 10 0 5\n",
+    );
+    test_plain_str(
+        "x: u8 = 200; print(max(x, 100), min(x, 3), sign(x), abs(x)); y: i8 = 0 - 3; print(sign(y));",
+        "200 3 1 200\n-1\n",
+    );
+    // Math on literals may be used as any int type.
+    test_plain_str("x: u8 = max(1, 2); print(x);", "2\n");
+}
+
+#[test]
+fn test_runtime_math() {
+    test_plain_str(
+        "print(pow(2, 10), pow(0 - 3, 3), pow(5, 0), pow(1, 1000000000000000000), pow(2, 62));",
+        "1024 -27 1 1 4611686018427387904\n",
+    );
+    test_plain_str(
+        "print(gcd(12, 18), gcd(0 - 12, 18), gcd(0, 0), lcm(4, 6), lcm(0, 5), lcm(0 - 4, 6));",
+        "6 6 0 12 0 12\n",
+    );
+    test_plain_str(
+        "print(isqrt(0), isqrt(15), isqrt(16), isqrt(9223372036854775807));",
+        "0 3 4 3037000499\n",
+    );
+    test_plain_str(
+        "x: u64 = (0 - 1) as u64; print(gcd(x, x), isqrt(x));",
+        "18446744073709551615 4294967295\n",
+    );
+}
+
+#[test]
+fn test_math_overflow() {
+    let code = "x: i8 = 0 - 128; print(abs(x), gcd(x, 0), pow(x, 3), lcm(x, 3));";
+    test_args(&["--overflow", "wrap", "-e", code], "-128 -128 0 -128\n");
+    test_args(
+        &["--overflow", "saturate", "-e", code],
+        "127 127 -128 127\n",
+    );
+    test_args(
+        &[
+            "--overflow",
+            "saturate",
+            "-e",
+            "print(pow(2, 100), pow(0 - 2, 101));",
+        ],
+        "9223372036854775807 -9223372036854775808\n",
+    );
+    test_args_fail(
+        &[
+            "--overflow",
+            "trap",
+            "-e",
+            "x: i8 = 0 - 128; print(abs(x));",
+        ],
+        "integer overflow in `abs`",
+    );
+    test_args_fail(
+        &["--overflow", "trap", "-e", "print(pow(3, 40));"],
+        "integer overflow in `*`",
+    );
+}
+
+#[test]
+fn test_math_errors() {
+    test_plain_str(
+        r#"
+try { pow(2, 0 - 1); } catch (e) { print(e); }
+try { isqrt(0 - 1); } catch (e) { print(e); }
+try { clamp(1, 5, 2); } catch (e) { print(e); }
+"#,
+        "negative exponent in pow()\nisqrt() of negative number -1\nclamp() lower bound is above the upper bound\n",
+    );
+    test_args_fail(&["-e", "min(1);"], "min() takes 2 arguments, not 1.");
+    test_args_fail(&["-e", "abs(1, 2);"], "abs() takes 1 argument, not 2.");
+    test_args_fail(
+        &["-e", "x: u8 = 1; y: i8 = 2; print(max(x, y));"],
+        "Expecting u8 but found i8",
+    );
+}
@@ -0,0 +1,61 @@
+mod common;
+
+use common::{test_args_fail, test_plain_str};
+
+#[test]
+fn test_string_builtins() {
+    test_plain_str(
+        r#"
+t = trim("  Hello, World  ");
+print(t, len(t));
+print(substr(t, 7, 5), find(t, "World"), find(t, "x"), find("héllo", "l"));
+print(replace(t, "l", "L"), upper(t), lower(t));
+print(starts_with(t, "Hell"), starts_with(t, "hell"), repeat("ab", 3));
+"#,
+        "Hello, World 12\nWorld 7 -1 2\nHeLLo, WorLd HELLO, WORLD hello, world\n1 0 ababab\n",
+    );
+    test_plain_str(
+        "print(substr(\"héllo\", 1, 3), substr(\"abc\", 3, 0), repeat(\"x\", 0), \"|\");",
+        "éll   |\n",
+    );
+}
+
+#[test]
+fn test_split() {
+    test_plain_str(
+        "p = split(\"a,b,,c\", \",\"); print(len(p), p, p[3]);",
+        "4 [a, b, , c] c\n",
+    );
+    test_plain_str(
+        "print(split(\"1 + 2 + 3\", \" + \"), len(split(\"\", \",\")));",
+        "[1, 2, 3] 1\n",
+    );
+}
+
+#[test]
+fn test_to_string() {
+    test_plain_str(
+        "x: u8 = 255; y: i8 = 0 - 5; print(to_string(x), to_string(y), to_string(123n * 1000000000000000000000n));",
+        "255 -5 123000000000000000000000\n",
+    );
+    test_plain_str(
+        "s = to_string(0 - 1234); print(len(s), parse_int(s) * 2);",
+        "5 -2468\n",
+    );
+}
+
+#[test]
+fn test_string_errors() {
+    test_plain_str(
+        r#"
+try { substr("abc", 2, 2); } catch (e) { print(e); }
+try { repeat("x", 0 - 1); } catch (e) { print(e); }
+try { split("abc", ""); } catch (e) { print(e); }
+try { repeat("ab", 9223372036854775807); } catch (e) { print(e); }
+"#,
+        "substring of 2 characters from 2 out of range for length 3\nnegative count -1 in repeat()\nempty separator in split()\ncount 9223372036854775807 in repeat() is too large\n",
+    );
+    test_args_fail(&["-e", "trim();"], "trim() takes 1 argument, not 0.");
+    test_args_fail(&["-e", "upper(1);"], "Expecting a string");
+    test_args_fail(&["-e", "to_string(\"1\");"], "Expecting an integer");
+}
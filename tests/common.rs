@@ -1,6 +1,34 @@
+#![allow(dead_code)]
+
+use std::process::Command;
+
+// Most of the tests were written for the original padded int printing, so they ask for it.
+fn fizzbuzz_bin() -> Command {
+    let mut cmd = test_bin::get_test_bin("fizzbuzz");
+    cmd.arg("--padded-ints");
+    cmd
+}
+
+// Run with the default int printing.
+pub fn test_plain_str(input: &str, expected: &str) {
+    test_cmd(test_bin::get_test_bin("fizzbuzz"), &["-e", input], expected);
+}
+
 pub fn test_str(input: &str, expected: &str) {
-    let output = test_bin::get_test_bin("fizzbuzz")
-        .args(&["-e", input])
+    test_args(&["-e", input], expected);
+}
+
+pub fn test_file(path: &str, expected: &str) {
+    test_args(&[path], expected);
+}
+
+pub fn test_args(args: &[&str], expected: &str) {
+    test_cmd(fizzbuzz_bin(), args, expected);
+}
+
+fn test_cmd(mut cmd: Command, args: &[&str], expected: &str) {
+    let output = cmd
+        .args(args)
         .output()
         .expect("Failed to run `fizzbuzz` binary.");
 
@@ -12,9 +40,108 @@ pub fn test_str(input: &str, expected: &str) {
     let output_str = String::from_utf8_lossy(&output.stdout);
     if output_str != expected {
         println!("TEST       : test_str");
-        println!(" IN        : '{}'", input);
+        println!(" IN        : '{}'", args.join(" "));
         println!(" EXPECTING : '{}'", expected);
         println!(" GOT       : '{}'", output_str);
         panic!("TEST test_str failed.");
     }
 }
+
+// Expect the run to fail with `expected_err` somewhere in its stderr.
+pub fn test_args_fail(args: &[&str], expected_err: &str) {
+    let output = fizzbuzz_bin()
+        .args(args)
+        .output()
+        .expect("Failed to run `fizzbuzz` binary.");
+
+    let err_str = String::from_utf8_lossy(&output.stderr);
+    if output.status.success() || !err_str.contains(expected_err) {
+        println!("TEST       : test_args_fail");
+        println!(" IN        : '{}'", args.join(" "));
+        println!(" EXPECTING : '{}'", expected_err);
+        println!(" GOT       : '{}' ({})", err_str, output.status);
+        panic!("TEST test_args_fail failed.");
+    }
+}
+
+// Expect the run to exit with `expected_status` and print `expected` to stdout.
+pub fn test_args_status(args: &[&str], expected: &str, expected_status: i32) {
+    let output = fizzbuzz_bin()
+        .args(args)
+        .output()
+        .expect("Failed to run `fizzbuzz` binary.");
+
+    let output_str = String::from_utf8_lossy(&output.stdout);
+    if output_str != expected || output.status.code() != Some(expected_status) {
+        println!("TEST       : test_args_status");
+        println!(" IN        : '{}'", args.join(" "));
+        println!(" EXPECTING : '{}' ({})", expected, expected_status);
+        println!(" GOT       : '{}' ({})", output_str, output.status);
+        println!("{}\n", String::from_utf8_lossy(&output.stderr));
+        panic!("TEST test_args_status failed.");
+    }
+}
+
+// Run `script` with the default int printing and `args` after it, returning the exit status, stdout
+// and stderr.
+pub fn run_full(script: &str, args: &[&str]) -> (Option<i32>, String, String) {
+    run_full_env(script, args, &[])
+}
+
+// As `run_full()`, with the environment variables `env` set.
+pub fn run_full_env(
+    script: &str,
+    args: &[&str],
+    env: &[(&str, &str)],
+) -> (Option<i32>, String, String) {
+    let output = test_bin::get_test_bin("fizzbuzz")
+        .args(["-e", script])
+        .args(args)
+        .envs(env.iter().copied())
+        .output()
+        .expect("Failed to run `fizzbuzz` binary.");
+    (
+        output.status.code(),
+        String::from_utf8_lossy(&output.stdout).into_owned(),
+        String::from_utf8_lossy(&output.stderr).into_owned(),
+    )
+}
+
+// Make an if-else which prints whether `expr` is true.
+pub fn wrap_in_ifelse(expr: &str) -> String {
+    let mut out_str = String::new();
+    out_str.push_str("if (");
+    out_str.push_str(expr);
+    out_str.push_str(r#") { print("True!"); } else { print("False!"); }"#);
+    out_str
+}
+
+// Run with `stdin` piped in.
+pub fn test_stdin(input: &str, stdin: &str, expected: &str) {
+    use std::io::Write;
+
+    let mut child = fizzbuzz_bin()
+        .args(["-e", input])
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .expect("Failed to run `fizzbuzz` binary.");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .expect("Failed to write to `fizzbuzz` stdin.");
+    let output = child.wait_with_output().unwrap();
+
+    let output_str = String::from_utf8_lossy(&output.stdout);
+    if output_str != expected {
+        println!("TEST       : test_stdin");
+        println!(" IN        : '{}' < '{}'", input, stdin);
+        println!(" EXPECTING : '{}'", expected);
+        println!(" GOT       : '{}' ({})", output_str, output.status);
+        println!("{}\n", String::from_utf8_lossy(&output.stderr));
+        panic!("TEST test_stdin failed.");
+    }
+}